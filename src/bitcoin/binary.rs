@@ -0,0 +1,162 @@
+// Locates the bitcoind executable used to spin up level nodes
+// and checks it is a Bitcoin Core version the levels support
+
+use std::{
+    env, fmt, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+const BITCOIND_ENV: &str = "BITCOIND_EXE";
+const CONFIG_FILE: &str = "btc-ctf.toml";
+
+/// Bitcoin Core major versions the levels are tested against
+pub const SUPPORTED_VERSIONS: RangeInclusive<u32> = 23..=28;
+
+/// Where the bitcoind executable was found
+#[derive(Clone, Copy)]
+pub enum BinarySource {
+    Flag,
+    Env,
+    Config,
+    Local,
+    Path,
+    Downloaded,
+}
+
+impl fmt::Display for BinarySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            Self::Flag => "--bitcoind flag",
+            Self::Env => "BITCOIND_EXE env var",
+            Self::Config => CONFIG_FILE,
+            Self::Local => "bin/bitcoind",
+            Self::Path => "PATH",
+            Self::Downloaded => "bitcoind crate download",
+        };
+        f.write_str(source)
+    }
+}
+
+pub struct BitcoindBinary {
+    pub path: PathBuf,
+    pub source: BinarySource,
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    bitcoind: Option<PathBuf>,
+}
+
+impl BitcoindBinary {
+    /// Finds bitcoind, in order of precedence from:
+    /// the `--bitcoind` flag, the `BITCOIND_EXE` env var, `btc-ctf.toml`,
+    /// `bin/bitcoind`, the `PATH` and the binaries downloaded by the bitcoind crate.
+    pub fn resolve(flag: Option<&Path>) -> Result<Self> {
+        if let Some(path) = flag {
+            return Self::existing(path.to_path_buf(), BinarySource::Flag);
+        }
+        if let Some(path) = env::var_os(BITCOIND_ENV) {
+            return Self::existing(path.into(), BinarySource::Env);
+        }
+        if let Some(path) = config_file_path()? {
+            return Self::existing(path, BinarySource::Config);
+        }
+
+        let local = env::current_dir()?.join("bin").join(exe_name());
+        if local.is_file() {
+            return Ok(Self {
+                path: local,
+                source: BinarySource::Local,
+            });
+        }
+
+        let in_path = env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(exe_name()))
+                .find(|path| path.is_file())
+        });
+        if let Some(path) = in_path {
+            return Ok(Self {
+                path,
+                source: BinarySource::Path,
+            });
+        }
+
+        if let Ok(path) = bitcoind::downloaded_exe_path() {
+            return Self::existing(path.into(), BinarySource::Downloaded);
+        }
+
+        bail!(
+            "Could not find bitcoind. Pass --bitcoind <PATH>, set {BITCOIND_ENV}, \
+             add `bitcoind = \"<PATH>\"` to {CONFIG_FILE} or put bitcoind in your PATH."
+        )
+    }
+
+    fn existing(path: PathBuf, source: BinarySource) -> Result<Self> {
+        if !path.is_file() {
+            bail!("bitcoind not found at {} (from {source})", path.display());
+        }
+        Ok(Self { path, source })
+    }
+
+    /// Runs `bitcoind -version` and returns the Bitcoin Core (major, minor) version
+    pub fn version(&self) -> Result<(u32, u32)> {
+        let output = Command::new(&self.path)
+            .arg("-version")
+            .output()
+            .with_context(|| format!("Failed to run {}", self.path.display()))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        // first line looks like "Bitcoin Core version v26.0.0"
+        let version = stdout
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(' ').next())
+            .map(|version| version.trim_start_matches('v'))
+            .context("Unexpected `bitcoind -version` output")?;
+        let mut parts = version.split('.').map(str::parse::<u32>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) => Ok((major, minor)),
+            _ => bail!("Unexpected bitcoind version: {version}"),
+        }
+    }
+
+    /// Fails if the binary is not a Bitcoin Core version the levels support
+    pub fn preflight(&self) -> Result<()> {
+        let (major, minor) = self.version()?;
+        if !SUPPORTED_VERSIONS.contains(&major) {
+            bail!(
+                "bitcoind v{major}.{minor} at {} is not supported, use Bitcoin Core v{} to v{}",
+                self.path.display(),
+                SUPPORTED_VERSIONS.start(),
+                SUPPORTED_VERSIONS.end()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn config_file_path() -> Result<Option<PathBuf>> {
+    let path = Path::new(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {CONFIG_FILE}"))?;
+    let config: ConfigFile = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file: {CONFIG_FILE}"))?;
+    Ok(config.bitcoind)
+}
+
+const fn exe_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "bitcoind.exe"
+    } else {
+        "bitcoind"
+    }
+}
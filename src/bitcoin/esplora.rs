@@ -0,0 +1,511 @@
+// Esplora API
+// Serves the level chain over the common Esplora REST endpoints so wallets and explorers
+// can be pointed at localhost instead of talking raw RPC. Built with `--features esplora`.
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use bitcoin::{
+    consensus::encode::serialize_hex, hex::DisplayHex, Address, Amount, Block, BlockHash, Network,
+    OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+};
+use bitcoind::bitcoincore_rpc::{Client, RpcApi};
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, sync::Mutex, task::JoinHandle};
+
+use super::CtfFramework;
+
+/// regtest port of the reference esplora server (electrs)
+const ESPLORA_PORT: u16 = 3002;
+
+/// Handle to the running server, it stops when the handle is dropped
+pub struct EsploraServer {
+    pub addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl EsploraServer {
+    /// Serves the chain of the node behind `client` on localhost,
+    /// on the esplora regtest port if it is free or on any free port otherwise.
+    pub async fn start(client: Client) -> Result<Self> {
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, ESPLORA_PORT)).await {
+            Ok(listener) => listener,
+            Err(_) => TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?,
+        };
+        let addr = listener.local_addr()?;
+
+        let explorer = Arc::new(Explorer {
+            client,
+            index: Mutex::new(Index::default()),
+        });
+        let router = Router::new()
+            .route("/address/:address/utxo", get(address_utxos))
+            .route("/tx/:txid", get(tx))
+            .route("/tx/:txid/hex", get(tx_hex))
+            .route("/tx/:txid/status", get(tx_status))
+            .route("/tx", post(broadcast))
+            .route("/block/:hash", get(block))
+            .route("/block/:hash/txids", get(block_txids))
+            .route("/block-height/:height", get(block_at_height))
+            .route("/blocks/tip/height", get(tip_height))
+            .route("/blocks/tip/hash", get(tip_hash))
+            .route("/mempool", get(mempool))
+            .route("/mempool/txids", get(mempool_txids))
+            .with_state(explorer);
+
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Ok(Self { addr, task })
+    }
+
+    pub fn print_details(&self) {
+        println!("{} http://{}", "Esplora API:".cyan(), self.addr);
+    }
+}
+
+impl Drop for EsploraServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl CtfFramework {
+    /// Serves the chain of the player's node over the Esplora API
+    pub async fn serve_esplora(&self) -> Result<EsploraServer> {
+        EsploraServer::start(self.session.rpc.node_client()?).await
+    }
+}
+
+/// Answers the requests on blocking threads, see `blocking`
+struct Explorer {
+    client: Client,
+    index: Mutex<Index>,
+}
+
+/// Confirmed transactions and outputs, the node has no address or transaction index
+#[derive(Default)]
+struct Index {
+    /// indexed blocks by height
+    blocks: Vec<BlockHash>,
+    /// block of every confirmed transaction
+    tx_blocks: HashMap<Txid, TxStatus>,
+    /// confirmed unspent outputs
+    utxos: HashMap<OutPoint, (TxOut, TxStatus)>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+struct TxStatus {
+    confirmed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_height: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_hash: Option<BlockHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_time: Option<u32>,
+}
+
+impl TxStatus {
+    const UNCONFIRMED: Self = Self {
+        confirmed: false,
+        block_height: None,
+        block_hash: None,
+        block_time: None,
+    };
+}
+
+impl Index {
+    /// Indexes the blocks mined since the last call.
+    /// After a reorg the whole chain is indexed again, regtest chains are small.
+    fn catch_up(&mut self, client: &Client) -> Result<()> {
+        let tip = client.get_block_count()?;
+        if let Some(last) = self.blocks.last() {
+            let height = self.blocks.len() as u64 - 1;
+            if height > tip || client.get_block_hash(height)? != *last {
+                *self = Self::default();
+            }
+        }
+        for height in self.blocks.len() as u64..=tip {
+            let hash = client.get_block_hash(height)?;
+            self.index_block(height, &client.get_block(&hash)?);
+        }
+        Ok(())
+    }
+
+    fn index_block(&mut self, height: u64, block: &Block) {
+        let status = TxStatus {
+            confirmed: true,
+            block_height: Some(height),
+            block_hash: Some(block.block_hash()),
+            block_time: Some(block.header.time),
+        };
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            self.tx_blocks.insert(txid, status);
+            if !tx.is_coinbase() {
+                for input in &tx.input {
+                    self.utxos.remove(&input.previous_output);
+                }
+            }
+            for (vout, output) in (0..).zip(&tx.output) {
+                self.utxos
+                    .insert(OutPoint { txid, vout }, (output.clone(), status));
+            }
+        }
+        self.blocks.push(block.block_hash());
+    }
+}
+
+impl Explorer {
+    fn catch_up(&self) -> Result<tokio::sync::MutexGuard<'_, Index>> {
+        let mut index = self.index.blocking_lock();
+        index.catch_up(&self.client)?;
+        Ok(index)
+    }
+
+    /// Finds a transaction in the mempool or the chain
+    fn transaction(&self, txid: Txid) -> Result<Option<(Transaction, TxStatus)>> {
+        let status = self.catch_up()?.tx_blocks.get(&txid).copied();
+        match status {
+            Some(status) => {
+                let tx = self
+                    .client
+                    .get_raw_transaction(&txid, status.block_hash.as_ref())?;
+                Ok(Some((tx, status)))
+            }
+            None => Ok(self
+                .client
+                .get_raw_transaction(&txid, None)
+                .ok()
+                .map(|tx| (tx, TxStatus::UNCONFIRMED))),
+        }
+    }
+
+    fn mempool(&self) -> Result<Vec<Transaction>> {
+        Ok(self
+            .client
+            .get_raw_mempool()?
+            .iter()
+            // a transaction can leave the mempool before it is fetched
+            .filter_map(|txid| self.client.get_raw_transaction(txid, None).ok())
+            .collect())
+    }
+
+    /// Esplora representation of a transaction
+    fn tx_json(&self, tx: &Transaction, status: TxStatus) -> Result<Value> {
+        let mut vin = Vec::new();
+        let mut input_value = Amount::ZERO;
+        for input in &tx.input {
+            let prevout = if tx.is_coinbase() {
+                None
+            } else {
+                let (prev_tx, _) = self
+                    .transaction(input.previous_output.txid)?
+                    .ok_or_else(|| anyhow!("Missing prevout {}", input.previous_output))?;
+                let prevout = prev_tx.output[input.previous_output.vout as usize].clone();
+                input_value += prevout.value;
+                Some(prevout)
+            };
+            vin.push(json!({
+                "txid": input.previous_output.txid,
+                "vout": input.previous_output.vout,
+                "prevout": prevout.as_ref().map(output_json),
+                "scriptsig": input.script_sig.to_hex_string(),
+                "scriptsig_asm": input.script_sig.to_asm_string(),
+                "witness": input
+                    .witness
+                    .iter()
+                    .map(DisplayHex::to_lower_hex_string)
+                    .collect::<Vec<_>>(),
+                "is_coinbase": tx.is_coinbase(),
+                "sequence": input.sequence.0,
+            }));
+        }
+        let output_value: Amount = tx.output.iter().map(|output| output.value).sum();
+        let fee = if tx.is_coinbase() {
+            Amount::ZERO
+        } else {
+            input_value.checked_sub(output_value).unwrap_or_default()
+        };
+
+        Ok(json!({
+            "txid": tx.compute_txid(),
+            "version": tx.version.0,
+            "locktime": tx.lock_time.to_consensus_u32(),
+            "vin": vin,
+            "vout": tx.output.iter().map(output_json).collect::<Vec<_>>(),
+            "size": tx.total_size(),
+            "weight": tx.weight().to_wu(),
+            "fee": fee.to_sat(),
+            "status": status,
+        }))
+    }
+
+    fn block_json(&self, hash: BlockHash) -> Result<Value> {
+        let block = self.client.get_block(&hash)?;
+        let header = self.client.get_block_header_info(&hash)?;
+        Ok(json!({
+            "id": hash,
+            "height": header.height,
+            "version": block.header.version.to_consensus(),
+            "timestamp": block.header.time,
+            "tx_count": block.txdata.len(),
+            "size": block.total_size(),
+            "weight": block.weight().to_wu(),
+            "merkle_root": block.header.merkle_root,
+            "previousblockhash": block.header.prev_blockhash,
+            "mediantime": header.median_time,
+            "nonce": block.header.nonce,
+            "bits": block.header.bits.to_consensus(),
+            "difficulty": header.difficulty,
+        }))
+    }
+}
+
+fn output_json(output: &TxOut) -> Value {
+    let script = &output.script_pubkey;
+    json!({
+        "scriptpubkey": script.to_hex_string(),
+        "scriptpubkey_asm": script.to_asm_string(),
+        "scriptpubkey_type": script_type(script),
+        "scriptpubkey_address": Address::from_script(script, Network::Regtest).ok(),
+        "value": output.value.to_sat(),
+    })
+}
+
+fn script_type(script: &Script) -> &'static str {
+    if script.is_p2pkh() {
+        "p2pkh"
+    } else if script.is_p2sh() {
+        "p2sh"
+    } else if script.is_p2wpkh() {
+        "v0_p2wpkh"
+    } else if script.is_p2wsh() {
+        "v0_p2wsh"
+    } else if script.is_p2tr() {
+        "v1_p2tr"
+    } else if script.is_op_return() {
+        "op_return"
+    } else {
+        "unknown"
+    }
+}
+
+/// Errors are returned as plain text, like esplora does
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        Self(StatusCode::BAD_REQUEST, err.into().to_string())
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+fn not_found(what: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("{what} not found"))
+}
+
+/// Runs `query` on a blocking thread, node calls and waiting for the index must not
+/// stall the async runtime serving the other requests
+async fn blocking<T: Send + 'static>(
+    explorer: Arc<Explorer>,
+    query: impl FnOnce(&Explorer) -> ApiResult<T> + Send + 'static,
+) -> ApiResult<T> {
+    tokio::task::spawn_blocking(move || query(&explorer)).await?
+}
+
+async fn address_utxos(
+    State(explorer): State<Arc<Explorer>>,
+    Path(address): Path<String>,
+) -> ApiResult<Json<Vec<Value>>> {
+    let script: ScriptBuf = address
+        .parse::<Address<_>>()?
+        .require_network(Network::Regtest)?
+        .script_pubkey();
+
+    blocking(explorer, move |explorer| {
+        let mut utxos: HashMap<OutPoint, (TxOut, TxStatus)> = explorer
+            .catch_up()?
+            .utxos
+            .iter()
+            .filter(|(_, (output, _))| output.script_pubkey == script)
+            .map(|(outpoint, utxo)| (*outpoint, utxo.clone()))
+            .collect();
+        // outputs spent and created by the mempool
+        for tx in explorer.mempool()? {
+            for input in &tx.input {
+                utxos.remove(&input.previous_output);
+            }
+            let txid = tx.compute_txid();
+            for (vout, output) in (0..).zip(tx.output) {
+                if output.script_pubkey == script {
+                    utxos.insert(OutPoint { txid, vout }, (output, TxStatus::UNCONFIRMED));
+                }
+            }
+        }
+
+        Ok(Json(
+            utxos
+                .into_iter()
+                .map(|(outpoint, (output, status))| {
+                    json!({
+                        "txid": outpoint.txid,
+                        "vout": outpoint.vout,
+                        "status": status,
+                        "value": output.value.to_sat(),
+                    })
+                })
+                .collect(),
+        ))
+    })
+    .await
+}
+
+async fn tx(
+    State(explorer): State<Arc<Explorer>>,
+    Path(txid): Path<Txid>,
+) -> ApiResult<Json<Value>> {
+    blocking(explorer, move |explorer| {
+        let (tx, status) = explorer
+            .transaction(txid)?
+            .ok_or_else(|| not_found("Transaction"))?;
+        Ok(Json(explorer.tx_json(&tx, status)?))
+    })
+    .await
+}
+
+async fn tx_hex(
+    State(explorer): State<Arc<Explorer>>,
+    Path(txid): Path<Txid>,
+) -> ApiResult<String> {
+    blocking(explorer, move |explorer| {
+        let (tx, _) = explorer
+            .transaction(txid)?
+            .ok_or_else(|| not_found("Transaction"))?;
+        Ok(serialize_hex(&tx))
+    })
+    .await
+}
+
+async fn tx_status(
+    State(explorer): State<Arc<Explorer>>,
+    Path(txid): Path<Txid>,
+) -> ApiResult<Json<TxStatus>> {
+    blocking(explorer, move |explorer| {
+        let (_, status) = explorer
+            .transaction(txid)?
+            .ok_or_else(|| not_found("Transaction"))?;
+        Ok(Json(status))
+    })
+    .await
+}
+
+async fn broadcast(State(explorer): State<Arc<Explorer>>, body: String) -> ApiResult<String> {
+    blocking(explorer, move |explorer| {
+        Ok(explorer
+            .client
+            .send_raw_transaction(body.trim())?
+            .to_string())
+    })
+    .await
+}
+
+async fn block(
+    State(explorer): State<Arc<Explorer>>,
+    Path(hash): Path<BlockHash>,
+) -> ApiResult<Json<Value>> {
+    blocking(explorer, move |explorer| {
+        Ok(Json(explorer.block_json(hash)?))
+    })
+    .await
+}
+
+async fn block_txids(
+    State(explorer): State<Arc<Explorer>>,
+    Path(hash): Path<BlockHash>,
+) -> ApiResult<Json<Vec<Txid>>> {
+    blocking(explorer, move |explorer| {
+        let block = explorer.client.get_block(&hash)?;
+        Ok(Json(
+            block.txdata.iter().map(Transaction::compute_txid).collect(),
+        ))
+    })
+    .await
+}
+
+async fn block_at_height(
+    State(explorer): State<Arc<Explorer>>,
+    Path(height): Path<u64>,
+) -> ApiResult<String> {
+    blocking(explorer, move |explorer| {
+        let hash = explorer
+            .client
+            .get_block_hash(height)
+            .map_err(|_| not_found("Block"))?;
+        Ok(hash.to_string())
+    })
+    .await
+}
+
+async fn tip_height(State(explorer): State<Arc<Explorer>>) -> ApiResult<String> {
+    blocking(explorer, |explorer| {
+        Ok(explorer.client.get_block_count()?.to_string())
+    })
+    .await
+}
+
+async fn tip_hash(State(explorer): State<Arc<Explorer>>) -> ApiResult<String> {
+    blocking(explorer, |explorer| {
+        Ok(explorer.client.get_best_block_hash()?.to_string())
+    })
+    .await
+}
+
+/// Mempool summary, the fee histogram is left empty
+async fn mempool(State(explorer): State<Arc<Explorer>>) -> ApiResult<Json<Value>> {
+    blocking(explorer, |explorer| {
+        let mut vsize = 0;
+        let mut total_fee = 0;
+        let entries: HashMap<Txid, Value> =
+            explorer.client.call("getrawmempool", &[json!(true)])?;
+        for entry in entries.values() {
+            vsize += entry["vsize"].as_u64().unwrap_or_default();
+            let fee = entry["fees"]["base"].as_f64().unwrap_or_default();
+            total_fee += Amount::from_btc(fee)?.to_sat();
+        }
+        Ok(Json(json!({
+            "count": entries.len(),
+            "vsize": vsize,
+            "total_fee": total_fee,
+            "fee_histogram": [],
+        })))
+    })
+    .await
+}
+
+async fn mempool_txids(State(explorer): State<Arc<Explorer>>) -> ApiResult<Json<Vec<Txid>>> {
+    blocking(explorer, |explorer| {
+        Ok(Json(explorer.client.get_raw_mempool()?))
+    })
+    .await
+}
@@ -0,0 +1,266 @@
+// Chain Events
+// Watches the level node and turns what happens on it into typed events levels can subscribe to.
+// Changes are picked up from the ZMQ notifications of the node, with RPC polling as a fallback.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddrV4,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use bitcoin::{Block, BlockHash, OutPoint, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::{Client, RpcApi};
+use tokio::{sync::broadcast, task::JoinHandle};
+use zeromq::{Socket, SocketRecv, SubSocket};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// with ZMQ up the node is still polled once in a while in case a notification got lost
+const ZMQ_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const ZMQ_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Something that happened on the level node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// a transaction was accepted into the mempool
+    TxEnteredMempool(Txid),
+    /// `replaced` left the mempool because `replacement` spends some of the same outpoints
+    TxReplaced {
+        replaced: Txid,
+        replacement: Txid,
+    },
+    BlockConnected {
+        hash: BlockHash,
+        height: u64,
+    },
+    BlockDisconnected {
+        hash: BlockHash,
+        height: u64,
+    },
+    /// `outpoint` was spent by `spender`, either in the mempool or in a block
+    OutpointSpent {
+        outpoint: OutPoint,
+        spender: Txid,
+    },
+}
+
+/// Handle to the background watcher, the task stops when the handle is dropped
+pub struct ChainWatcher {
+    events: broadcast::Sender<ChainEvent>,
+    task: JoinHandle<()>,
+}
+
+impl ChainWatcher {
+    /// Spawns the watcher task, blocks connected after this call are reported and so is
+    /// every mempool transaction, including the ones already there when watching starts.
+    /// The returned receiver is subscribed before the task starts, so it gets every event.
+    /// Without ZMQ endpoints (or if they can't be reached) the node is polled.
+    pub fn start(
+        client: Client,
+        zmq_endpoints: Vec<SocketAddrV4>,
+    ) -> Result<(Self, broadcast::Receiver<ChainEvent>)> {
+        let mut view = ChainView::new(&client)?;
+        let (events, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        // the view starts with an empty mempool, so the first sync reports what is in it
+        for event in view.sync(&client)? {
+            let _ = events.send(event);
+        }
+        let task = tokio::spawn(watch(Arc::new(client), view, zmq_endpoints, events.clone()));
+        Ok((Self { events, task }, receiver))
+    }
+
+    /// A new receiver of every event from now on
+    #[allow(dead_code)]
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+}
+
+impl Drop for ChainWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn watch(
+    client: Arc<Client>,
+    mut view: ChainView,
+    zmq_endpoints: Vec<SocketAddrV4>,
+    events: broadcast::Sender<ChainEvent>,
+) {
+    let mut notifications = subscribe_zmq(&zmq_endpoints).await;
+    let mut ticker = tokio::time::interval(if notifications.is_some() {
+        ZMQ_POLL_INTERVAL
+    } else {
+        POLL_INTERVAL
+    });
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            received = next_notification(&mut notifications) => {
+                if !received {
+                    // ZMQ went away, keep going by polling
+                    notifications = None;
+                    ticker = tokio::time::interval(POLL_INTERVAL);
+                }
+            }
+        }
+
+        // the node is queried on a blocking thread, the view comes back with the result
+        let client = Arc::clone(&client);
+        let Ok((synced, new_events)) = tokio::task::spawn_blocking(move || {
+            let new_events = view.sync(&client);
+            (view, new_events)
+        })
+        .await
+        else {
+            break;
+        };
+        view = synced;
+
+        // a failed sync is simply retried on the next notification or tick
+        if let Ok(new_events) = new_events {
+            for event in new_events {
+                let _ = events.send(event);
+            }
+        }
+    }
+}
+
+/// Subscribes to every topic the node publishes, `None` if ZMQ is unavailable
+async fn subscribe_zmq(endpoints: &[SocketAddrV4]) -> Option<SubSocket> {
+    if endpoints.is_empty() {
+        return None;
+    }
+    let mut socket = SubSocket::new();
+    for endpoint in endpoints {
+        let endpoint = format!("tcp://{endpoint}");
+        tokio::time::timeout(ZMQ_CONNECT_TIMEOUT, socket.connect(&endpoint))
+            .await
+            .ok()?
+            .ok()?;
+    }
+    socket.subscribe("").await.ok()?;
+    Some(socket)
+}
+
+/// Waits for the next ZMQ message, false once the socket failed.
+/// Never completes when there is no socket.
+async fn next_notification(socket: &mut Option<SubSocket>) -> bool {
+    match socket {
+        // the payload isn't needed, the node is queried for the new state
+        Some(socket) => socket.recv().await.is_ok(),
+        None => std::future::pending().await,
+    }
+}
+
+/// What the watcher last saw of the node
+struct ChainView {
+    /// best chain by height, starting at the tip when watching started
+    blocks: BTreeMap<u64, BlockHash>,
+    start_height: u64,
+    mempool: HashSet<Txid>,
+    /// outpoints spent by mempool transactions
+    spenders: HashMap<OutPoint, Txid>,
+}
+
+impl ChainView {
+    fn new(client: &Client) -> Result<Self> {
+        let start_height = client.get_block_count()?;
+        Ok(Self {
+            blocks: BTreeMap::from([(start_height, client.get_block_hash(start_height)?)]),
+            start_height,
+            mempool: HashSet::new(),
+            spenders: HashMap::new(),
+        })
+    }
+
+    /// Catches up with the node and returns what changed since the last sync
+    fn sync(&mut self, client: &Client) -> Result<Vec<ChainEvent>> {
+        // fetch everything first so a failed RPC leaves the view untouched
+        let (fork_height, connected) = self.new_blocks(client)?;
+        let mempool: HashSet<Txid> = client.get_raw_mempool()?.into_iter().collect();
+        let entered: Vec<Transaction> = mempool
+            .difference(&self.mempool)
+            // a transaction can leave the mempool before it is fetched
+            .filter_map(|txid| client.get_raw_transaction(txid, None).ok())
+            .collect();
+
+        let mut events = Vec::new();
+        let disconnected = self.blocks.split_off(&(fork_height + 1));
+        for (height, hash) in disconnected.into_iter().rev() {
+            events.push(ChainEvent::BlockDisconnected { hash, height });
+        }
+
+        for (height, block) in connected {
+            let hash = block.block_hash();
+            events.push(ChainEvent::BlockConnected { hash, height });
+            // the coinbase spends nothing, spends seen in the mempool were already reported
+            for tx in block.txdata.iter().skip(1) {
+                if !self.mempool.contains(&tx.compute_txid()) {
+                    self.record_spends(tx, &mempool, &mut events);
+                }
+            }
+            self.blocks.insert(height, hash);
+        }
+
+        for tx in &entered {
+            events.push(ChainEvent::TxEnteredMempool(tx.compute_txid()));
+            self.record_spends(tx, &mempool, &mut events);
+        }
+
+        self.spenders.retain(|_, spender| mempool.contains(spender));
+        self.mempool = mempool;
+        Ok(events)
+    }
+
+    /// Walks back from the node's tip to the last block already known.
+    /// Returns the height of that block and the blocks on top of it, lowest first.
+    fn new_blocks(&self, client: &Client) -> Result<(u64, Vec<(u64, Block)>)> {
+        let mut connected = Vec::new();
+        let mut hash = client.get_best_block_hash()?;
+        let fork_height = loop {
+            let header = client.get_block_header_info(&hash)?;
+            let height = header.height as u64;
+            if height < self.start_height || self.blocks.get(&height) == Some(&hash) {
+                break height;
+            }
+            connected.push((height, client.get_block(&hash)?));
+            match header.previous_block_hash {
+                Some(previous) => hash = previous,
+                None => break 0,
+            }
+        };
+        connected.reverse();
+        Ok((fork_height, connected))
+    }
+
+    /// Reports the outpoints spent by `tx` and the mempool transactions it replaced
+    fn record_spends(
+        &mut self,
+        tx: &Transaction,
+        mempool: &HashSet<Txid>,
+        events: &mut Vec<ChainEvent>,
+    ) {
+        let txid = tx.compute_txid();
+        let mut replaced = HashSet::new();
+        for input in &tx.input {
+            let outpoint = input.previous_output;
+            if let Some(previous) = self.spenders.insert(outpoint, txid) {
+                if previous != txid && !mempool.contains(&previous) && replaced.insert(previous) {
+                    events.push(ChainEvent::TxReplaced {
+                        replaced: previous,
+                        replacement: txid,
+                    });
+                }
+            }
+            events.push(ChainEvent::OutpointSpent {
+                outpoint,
+                spender: txid,
+            });
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Block Production
+// Background task mining blocks on the level node so confirmations, races and timelocks happen
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use bitcoin::{Address, BlockHash};
+use bitcoind::bitcoincore_rpc::{Client, RpcApi};
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+/// When the miner produces blocks
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum MiningMode {
+    /// a block every interval
+    Interval(Duration),
+    /// blocks arrive as a poisson process with the given mean interval, like mainnet
+    Poisson(Duration),
+    /// blocks are only mined through [`Miner::mine`]
+    OnDemand,
+    /// a block whenever the mempool has transactions, checked every interval
+    MempoolNonEmpty(Duration),
+}
+
+enum MinerCommand {
+    Pause,
+    Resume,
+    SetMode(MiningMode),
+    Mine(u64, oneshot::Sender<Result<Vec<BlockHash>>>),
+}
+
+/// Handle to the background miner, the task stops when the handle is dropped
+pub struct Miner {
+    commands: mpsc::UnboundedSender<MinerCommand>,
+    task: JoinHandle<()>,
+}
+
+#[allow(dead_code)]
+impl Miner {
+    /// Spawns the miner task, mined coins go to `address`.
+    /// Random block intervals are drawn from `rng`, so a seeded level mines the same way.
+    pub fn start(client: Client, address: Address, mode: MiningMode, rng: ChaCha20Rng) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(mine_blocks(Arc::new(client), address, mode, rng, receiver));
+        Self { commands, task }
+    }
+
+    pub fn pause(&self) {
+        let _ = self.commands.send(MinerCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.commands.send(MinerCommand::Resume);
+    }
+
+    pub fn set_mode(&self, mode: MiningMode) {
+        let _ = self.commands.send(MinerCommand::SetMode(mode));
+    }
+
+    /// Mines `blocks` blocks right away, whatever the mode or pause state
+    pub async fn mine(&self, blocks: u64) -> Result<Vec<BlockHash>> {
+        let (reply, mined) = oneshot::channel();
+        self.commands
+            .send(MinerCommand::Mine(blocks, reply))
+            .map_err(|_| anyhow!("Miner has stopped"))?;
+        mined.await?
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn mine_blocks(
+    client: Arc<Client>,
+    address: Address,
+    mut mode: MiningMode,
+    mut rng: ChaCha20Rng,
+    mut commands: mpsc::UnboundedReceiver<MinerCommand>,
+) {
+    let mut paused = false;
+    loop {
+        let wait = if paused {
+            None
+        } else {
+            next_block_in(mode, &mut rng)
+        };
+        let sleep = tokio::time::sleep(wait.unwrap_or_default());
+
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(MinerCommand::Pause) => paused = true,
+                Some(MinerCommand::Resume) => paused = false,
+                Some(MinerCommand::SetMode(new_mode)) => mode = new_mode,
+                Some(MinerCommand::Mine(blocks, reply)) => {
+                    let _ = reply.send(generate(&client, &address, blocks).await);
+                }
+                None => break,
+            },
+            () = sleep, if wait.is_some() => {
+                if let MiningMode::MempoolNonEmpty(_) = mode {
+                    let mempool = blocking(&client, |client| Ok(client.get_raw_mempool()?)).await;
+                    if mempool.map_or(true, |mempool| mempool.is_empty()) {
+                        continue;
+                    }
+                }
+                // a failed block is simply retried on the next tick
+                let _ = generate(&client, &address, 1).await;
+            }
+        }
+    }
+}
+
+async fn generate(client: &Arc<Client>, address: &Address, blocks: u64) -> Result<Vec<BlockHash>> {
+    let address = address.clone();
+    blocking(client, move |client| {
+        Ok(client.generate_to_address(blocks, &address)?)
+    })
+    .await
+}
+
+/// Runs a node call on a blocking thread, mining a block can take a while
+async fn blocking<T: Send + 'static>(
+    client: &Arc<Client>,
+    call: impl FnOnce(&Client) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let client = Arc::clone(client);
+    tokio::task::spawn_blocking(move || call(&client)).await?
+}
+
+/// Time until the next block, `None` if blocks are only mined on demand
+fn next_block_in(mode: MiningMode, rng: &mut impl Rng) -> Option<Duration> {
+    match mode {
+        MiningMode::Interval(interval) | MiningMode::MempoolNonEmpty(interval) => Some(interval),
+        MiningMode::Poisson(mean) => {
+            // inter-arrival times of a poisson process are exponentially distributed
+            let uniform: f64 = rng.gen_range(f64::EPSILON..1.0);
+            Some(mean.mul_f64(-uniform.ln()))
+        }
+        MiningMode::OnDemand => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn poisson_intervals_follow_the_seed() {
+        let mode = MiningMode::Poisson(Duration::from_secs(10));
+        let intervals = |seed| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            (0..8)
+                .map(|_| next_block_in(mode, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(intervals(1), intervals(1));
+        assert_ne!(intervals(1), intervals(2));
+    }
+}
@@ -1,7 +1,44 @@
 // mod file
 
+mod binary;
+#[cfg(feature = "esplora")]
+mod esplora;
+mod events;
+mod miner;
 mod regtest;
+mod reorg;
+mod rpc;
+mod session;
+mod signer;
+mod snapshot;
+mod topology;
 mod transaction;
+mod wallet;
+mod weak_nonce;
 
-pub use regtest::CtfFramework;
-pub use transaction::{add_signature, TransactionBuilder};
+pub use binary::{BitcoindBinary, SUPPORTED_VERSIONS};
+pub use events::{ChainEvent, ChainWatcher};
+pub use miner::{Miner, MiningMode};
+pub use regtest::{CtfFramework, FrameworkConfig};
+#[allow(unused_imports)] // no level reorgs the chain yet
+pub use reorg::{Reorg, ReorgReport};
+pub use rpc::RpcConnection;
+pub use session::{PeerNode, Session};
+#[allow(unused_imports)] // not every helper has a caller yet
+pub use signer::{
+    invert, scalar_from_u64, schnorr_challenge, sign_ecdsa_with_nonce, sign_schnorr_with_nonce,
+    HonestSigner, NonceSource, Signer,
+};
+pub use snapshot::ChainTemplate;
+pub use topology::{NodeRole, Topology};
+pub use transaction::{add_signature, tapscript_tree, TransactionBuilder};
+#[allow(unused_imports)] // for levels spending through a tapscript leaf
+pub use transaction::{
+    control_block, script_path_sighash, set_script_path_witness, sign_script_path,
+};
+pub use wallet::PlayerWallet;
+pub use weak_nonce::SmallNonce;
+#[allow(unused_imports)] // flaws not used by any level yet
+pub use weak_nonce::{
+    BiasedNonce, CounterNonce, KnownBits, LinearNonce, MessageNonce, ReusedNonce,
+};
@@ -1,37 +1,237 @@
 // Starts Regtest Node providing a temp Config
-// Every Level Setup Includes running a Clean Regtest Node
+// Every Level Setup Includes running a Clean Regtest Node in its own session directory,
+// along with the other nodes of the level network if the level declares some
 
-use anyhow::{Ok, Result};
-use bitcoind::{BitcoinD, Conf};
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::{Keypair, Secp256k1},
+    taproot::TaprootSpendInfo,
+    Address, Amount, Network, Transaction, TxOut,
+};
+use bitcoind::{bitcoincore_rpc::RpcApi, BitcoinD, Conf, P2P};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tokio::sync::broadcast;
+
+use super::{
+    add_signature, BitcoindBinary, ChainEvent, ChainTemplate, ChainWatcher, HonestSigner, Miner,
+    MiningMode, NodeRole, PeerNode, PlayerWallet, RpcConnection, Session, Topology,
+    TransactionBuilder,
+};
+use crate::constrants::PROJECTED_FEE;
+
+/// Options used to spin up level nodes
+#[derive(Clone, Default)]
+pub struct FrameworkConfig {
+    /// bitcoind executable given on the command line
+    pub bitcoind: Option<PathBuf>,
+    /// seed the level is generated from, a random one if not given
+    pub seed: Option<u64>,
+}
 
 // might need state later!!
 pub struct CtfFramework {
+    /// node the player talks to, node 0 of the level network
     pub bitcoind: BitcoinD,
+    /// the other nodes of the level network, node `i` being `peers[i - 1]`
+    pub peers: Vec<BitcoinD>,
+    pub topology: Topology,
+    pub player: PlayerWallet,
+    pub session: Session,
+    /// seed every random choice of the level is derived from
+    pub seed: u64,
+    /// key the coins of the chain template are mined to
+    funding_key: Keypair,
+    /// height of the next template coinbase spent by [`Self::fund`]
+    next_coinbase: AtomicU64,
 }
 
 impl CtfFramework {
     /// Starts Regtest Node
-    /// with default config and bin/bitcoin/static being its data directory
-    /// 
+    /// with default config and a fresh bin/bitcoin/sessions/<id> being its data directory,
+    /// holding a copy of the chain built by `template`, and creates the player wallet.
+    /// The other nodes of `topology` start from the same chain under <id>/peers
+    /// and get wired up as the topology declares.
+    /// The session is registered so `btc-ctf rpc` and `btc-ctf sessions` can reach the node.
+    ///
     /// todo ? do we really need to store `bitcoind` isnt `bitcoin.client` enough ?
-    pub fn new() -> Result<Self> {
+    pub async fn new(
+        config: &FrameworkConfig,
+        template: &ChainTemplate,
+        topology: &Topology,
+    ) -> Result<Self> {
+        let binary = BitcoindBinary::resolve(config.bitcoind.as_deref())?;
+        binary.preflight()?;
+
+        let (id, datadir) = Session::reserve();
+        if let Err(err) = template.restore(&binary, &datadir) {
+            let _ = std::fs::remove_dir_all(&datadir);
+            return Err(err);
+        }
         let mut conf = Conf::default();
-        conf.staticdir = Some("bin/bitcoin/static".into());
-
-        let key = "BITCOIND_EXE";
-        let curr_dir_path = std::env::current_dir().unwrap();
-        let bitcoind_path = curr_dir_path.join("bin").join("bitcoind");
-        std::env::set_var(key, bitcoind_path);
-        let exe_path = bitcoind::exe_path().unwrap();
-        let bitcoind = BitcoinD::with_conf(exe_path, &conf).unwrap();
-        Ok(Self { bitcoind })
+        conf.staticdir = Some(datadir.clone());
+        conf.enable_zmq = true;
+        if topology.node_count() > 1 {
+            conf.p2p = P2P::Yes;
+        }
+
+        let started = BitcoinD::with_conf(&binary.path, &conf)
+            .with_context(|| format!("Failed to start {}", binary.path.display()))
+            .and_then(|bitcoind| {
+                let player = PlayerWallet::create(&bitcoind)?;
+                let session = Session::new(
+                    id,
+                    datadir.clone(),
+                    RpcConnection::new(&bitcoind, &player.name),
+                )?;
+                session.save()?;
+                Ok((bitcoind, player, session))
+            });
+        // without a session file `sessions --prune` would never find the directory,
+        // a node that did start was stopped when dropped
+        let (bitcoind, player, session) = match started {
+            Ok(started) => started,
+            Err(err) => {
+                let _ = std::fs::remove_dir_all(&datadir);
+                return Err(err);
+            }
+        };
+
+        let mut framework = Self {
+            bitcoind,
+            peers: Vec::new(),
+            topology: *topology,
+            player,
+            session,
+            seed: config.seed.unwrap_or_else(rand::random),
+            funding_key: template.funding_key(&Secp256k1::new()),
+            next_coinbase: AtomicU64::new(1),
+        };
+
+        // peers live inside the session directory so they get torn down along with it
+        for idx in 1..topology.node_count() {
+            let peer_dir = datadir.join("peers").join(idx.to_string());
+            template.restore(&binary, &peer_dir)?;
+            let mut conf = Conf::default();
+            conf.staticdir = Some(peer_dir);
+            conf.p2p = P2P::Yes;
+            let peer = BitcoinD::with_conf(&binary.path, &conf)
+                .with_context(|| format!("Failed to start node {idx}"))?;
+            // recorded so `btc-ctf sessions` stops peers along with the session
+            framework.session.peers.push(PeerNode::new(&peer)?);
+            framework.session.save()?;
+            framework.peers.push(peer);
+        }
+        if topology.groups.is_empty() {
+            framework.heal().await?;
+        } else {
+            framework.partition(topology.groups).await?;
+        }
+
+        Ok(framework)
     }
 
-    pub fn clean() -> Result<()> {
-        // deletes bin/bitcoin/static directory
-        if !std::path::Path::new("bin/bitcoin/static").exists() {
-            return Ok(());
+    /// Random generator for `purpose`, the same seed and purpose always give the same numbers
+    pub fn rng(&self, purpose: &str) -> ChaCha20Rng {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.seed.to_le_bytes());
+        engine.input(purpose.as_bytes());
+        ChaCha20Rng::from_seed(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Pays `outputs` from the next matured coinbase of the chain template and confirms them.
+    /// The transaction only depends on the template and `outputs`, so its txid is reproducible.
+    pub fn fund(&self, outputs: Vec<TxOut>) -> Result<Transaction> {
+        let client = &self.bitcoind.client;
+        let secp = Secp256k1::new();
+        let spend_info =
+            TaprootSpendInfo::new_key_spend(&secp, self.funding_key.x_only_public_key().0, None);
+        let funding_address = Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest);
+
+        let height = self.next_coinbase.fetch_add(1, Ordering::Relaxed);
+        let block = client.get_block(&client.get_block_hash(height)?)?;
+        let coinbase = &block.txdata[0];
+        let prevout = coinbase.output[0].clone();
+
+        // change goes back to the funding key
+        let total: Amount = outputs.iter().map(|output| output.value).sum();
+        let change = prevout
+            .value
+            .checked_sub(total + PROJECTED_FEE)
+            .context("Template coinbase can't cover the funding")?;
+        let mut tx_builder = TransactionBuilder::new(change);
+        tx_builder.transaction.output[0].script_pubkey = funding_address.script_pubkey();
+        tx_builder.add_input(coinbase.compute_txid(), 0);
+        let mut tx = tx_builder.build();
+        tx.output.extend(outputs);
+
+        add_signature(
+            &mut tx,
+            0,
+            &[&prevout],
+            self.funding_key.secret_key(),
+            Some(&spend_info.tap_tweak().to_scalar()),
+            &mut HonestSigner {
+                rng: self.rng("funding"),
+            },
+            &secp,
+        )?;
+        client.send_raw_transaction(&tx)?;
+        client.generate_to_address(1, &funding_address)?;
+        Ok(tx)
+    }
+
+    /// Starts a background miner on the first miner node of the network,
+    /// it stops once the returned handle is dropped
+    pub fn start_miner(&self, mode: MiningMode) -> Result<Miner> {
+        let idx = (0..self.topology.node_count())
+            .find(|&idx| self.role(idx) == Some(NodeRole::Miner))
+            .context("The level network has no miner node")?;
+        let address = self
+            .node(idx)?
+            .client
+            .get_new_address(Some("miner"), None)?
+            .require_network(Network::Regtest)?;
+        Ok(Miner::start(
+            self.node_client(idx)?,
+            address,
+            mode,
+            self.rng("miner"),
+        ))
+    }
+
+    /// Starts watching the node for chain and mempool events, it stops once the returned handle is dropped.
+    /// The receiver gets every event from the start.
+    pub fn watch_chain(&self) -> Result<(ChainWatcher, broadcast::Receiver<ChainEvent>)> {
+        let params = &self.bitcoind.params;
+        let zmq_endpoints = [
+            params.zmq_pub_raw_tx_socket,
+            params.zmq_pub_raw_block_socket,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        ChainWatcher::start(self.session.rpc.node_client()?, zmq_endpoints)
+    }
+}
+
+/// Tears down the regtest nodes on every exit path (error, panic or interrupt):
+/// stops `bitcoind` and its peers and removes its session data directory, leaving other sessions untouched.
+impl Drop for CtfFramework {
+    fn drop(&mut self) {
+        for peer in &mut self.peers {
+            let _ = peer.stop();
+        }
+        let _ = self.bitcoind.stop();
+        let workdir = self.bitcoind.workdir();
+        if workdir.exists() {
+            let _ = std::fs::remove_dir_all(workdir);
         }
-        Ok(std::fs::remove_dir_all("bin/bitcoin/static")?)
     }
 }
@@ -0,0 +1,252 @@
+// Reorg Injection
+// Replaces the tip of the chain with a competing branch, the basis of fee-sniping,
+// 0-conf and confirmation-depth levels.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use bitcoin::{consensus::encode::serialize_hex, BlockHash, Network, OutPoint, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::RpcApi;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::CtfFramework;
+
+/// A competing branch forking `depth` blocks below the tip
+pub struct Reorg {
+    /// blocks of the current chain replaced by the branch
+    pub depth: u64,
+    /// blocks in the branch, more than `depth` so it ends up with the most work
+    pub length: u64,
+    /// transactions confirmed in the first block of the branch, e.g. double spends
+    pub include: Vec<Transaction>,
+    /// transactions of the replaced blocks left out of the branch.
+    /// They go back to the mempool, unless they conflict with the branch.
+    pub exclude: HashSet<Txid>,
+}
+
+/// What a reorg changed
+#[allow(dead_code)]
+pub struct ReorgReport {
+    /// replaced blocks, old tip first
+    pub disconnected: Vec<BlockHash>,
+    /// blocks of the branch, new tip last
+    pub connected: Vec<BlockHash>,
+    /// transactions confirmed in the replaced blocks but not in the branch
+    pub unconfirmed: Vec<Txid>,
+    /// transactions, from the replaced blocks or the mempool, with an input spent
+    /// by another transaction of the branch. They can't confirm anymore.
+    pub double_spent: Vec<Txid>,
+}
+
+#[derive(Deserialize)]
+struct GeneratedBlock {
+    hash: BlockHash,
+}
+
+#[allow(dead_code)]
+impl Reorg {
+    /// The shortest branch replacing the last `depth` blocks, with the same transactions
+    pub fn new(depth: u64) -> Self {
+        Self {
+            depth,
+            length: depth + 1,
+            include: Vec::new(),
+            exclude: HashSet::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl CtfFramework {
+    /// Builds the branch described by `reorg` on the player's node and makes it the best chain.
+    /// The old blocks are invalidated while the branch is mined with `generateblock`,
+    /// then reconsidered, the branch stays active as it has more work.
+    /// Connected peers follow the reorg once they see the branch.
+    pub fn reorg(&self, reorg: &Reorg) -> Result<ReorgReport> {
+        if reorg.length <= reorg.depth {
+            bail!(
+                "A branch replacing {} blocks needs more than {} blocks",
+                reorg.depth,
+                reorg.depth
+            );
+        }
+        let client = &self.bitcoind.client;
+        let tip_height = client.get_block_count()?;
+        if reorg.depth == 0 || reorg.depth > tip_height {
+            bail!("Can't reorg {} blocks out of {tip_height}", reorg.depth);
+        }
+
+        let fork_height = tip_height - reorg.depth;
+        let mut disconnected = Vec::new();
+        let mut old_txs = Vec::new();
+        for height in (fork_height + 1)..=tip_height {
+            let hash = client.get_block_hash(height)?;
+            let block = client.get_block(&hash)?;
+            disconnected.push(hash);
+            old_txs.extend(block.txdata.into_iter().skip(1));
+        }
+        disconnected.reverse();
+
+        let first_block = branch_transactions(old_txs.clone(), reorg);
+        let address = client
+            .get_new_address(Some("miner"), None)?
+            .require_network(Network::Regtest)?;
+        // 0-conf transactions evicted by the branch only show up in a mempool diff
+        let mempool_before = client
+            .get_raw_mempool()?
+            .iter()
+            .map(|txid| client.get_raw_transaction(txid, None))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        client.invalidate_block(disconnected.last().expect("depth is at least 1"))?;
+        let mut connected = Vec::new();
+        let mut new_txids = HashSet::new();
+        let mut new_spends = HashSet::new();
+        for idx in 0..reorg.length {
+            let txs: &[Transaction] = if idx == 0 { &first_block } else { &[] };
+            let hexes: Vec<String> = txs.iter().map(serialize_hex).collect();
+            // generateblock confirms exactly the given transactions, mempool or not
+            let generated: GeneratedBlock =
+                client.call("generateblock", &[json!(address.to_string()), json!(hexes)])?;
+            connected.push(generated.hash);
+            for tx in txs {
+                new_txids.insert(tx.compute_txid());
+                new_spends.extend(tx.input.iter().map(|input| input.previous_output));
+            }
+        }
+        client.reconsider_block(disconnected.last().expect("depth is at least 1"))?;
+
+        let unconfirmed: Vec<&Transaction> = old_txs
+            .iter()
+            .filter(|tx| !new_txids.contains(&tx.compute_txid()))
+            .collect();
+        let mempool_after: HashSet<Txid> = client.get_raw_mempool()?.into_iter().collect();
+        let evicted = mempool_before.iter().filter(|tx| {
+            let txid = tx.compute_txid();
+            !mempool_after.contains(&txid) && !new_txids.contains(&txid)
+        });
+        let double_spent = unconfirmed
+            .iter()
+            .copied()
+            .chain(evicted)
+            .filter(|tx| spends_any(tx, &new_spends))
+            .map(Transaction::compute_txid)
+            .collect();
+
+        Ok(ReorgReport {
+            disconnected,
+            connected,
+            unconfirmed: unconfirmed.iter().map(|tx| tx.compute_txid()).collect(),
+            double_spent,
+        })
+    }
+}
+
+/// Transactions of the first block of the branch: the replaced transactions which are
+/// neither excluded, nor in conflict with `include`, nor descending from a dropped one,
+/// followed by `include`.
+fn branch_transactions(old_txs: Vec<Transaction>, reorg: &Reorg) -> Vec<Transaction> {
+    let included_spends: HashSet<OutPoint> = reorg
+        .include
+        .iter()
+        .flat_map(|tx| tx.input.iter().map(|input| input.previous_output))
+        .collect();
+
+    let mut dropped = reorg.exclude.clone();
+    let mut kept = Vec::new();
+    // blocks are in topological order, parents are seen before their children
+    for tx in old_txs {
+        let txid = tx.compute_txid();
+        let descends_from_dropped = tx
+            .input
+            .iter()
+            .any(|input| dropped.contains(&input.previous_output.txid));
+        if dropped.contains(&txid) || descends_from_dropped || spends_any(&tx, &included_spends) {
+            dropped.insert(txid);
+        } else {
+            kept.push(tx);
+        }
+    }
+    kept.extend(reorg.include.iter().cloned());
+    kept
+}
+
+fn spends_any(tx: &Transaction, outpoints: &HashSet<OutPoint>) -> bool {
+    tx.input
+        .iter()
+        .any(|input| outpoints.contains(&input.previous_output))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Amount, ScriptBuf, Sequence, TxIn,
+        TxOut, Witness,
+    };
+
+    use super::*;
+
+    /// A transaction spending `inputs`, `tag` keeps txids apart
+    fn tx(inputs: &[OutPoint], tag: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|&previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::default(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(tag),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn outpoint(byte: u8) -> OutPoint {
+        OutPoint::new(Txid::from_byte_array([byte; 32]), 0)
+    }
+
+    fn txids(txs: &[Transaction]) -> Vec<Txid> {
+        txs.iter().map(Transaction::compute_txid).collect()
+    }
+
+    #[test]
+    fn keeps_old_transactions_by_default() {
+        let old = vec![tx(&[outpoint(1)], 1), tx(&[outpoint(2)], 2)];
+        assert_eq!(
+            txids(&branch_transactions(old.clone(), &Reorg::new(1))),
+            txids(&old)
+        );
+    }
+
+    #[test]
+    fn drops_excluded_transactions_and_their_descendants() {
+        let parent = tx(&[outpoint(1)], 1);
+        let child = tx(&[OutPoint::new(parent.compute_txid(), 0)], 2);
+        let other = tx(&[outpoint(2)], 3);
+        let mut reorg = Reorg::new(1);
+        reorg.exclude.insert(parent.compute_txid());
+
+        let branch = branch_transactions(vec![parent, child, other.clone()], &reorg);
+        assert_eq!(txids(&branch), txids(&[other]));
+    }
+
+    #[test]
+    fn included_double_spends_replace_their_conflicts() {
+        let victim = tx(&[outpoint(1)], 1);
+        let victim_child = tx(&[OutPoint::new(victim.compute_txid(), 0)], 2);
+        let other = tx(&[outpoint(2)], 3);
+        let double_spend = tx(&[outpoint(1)], 4);
+        let mut reorg = Reorg::new(1);
+        reorg.include.push(double_spend.clone());
+
+        let branch = branch_transactions(vec![victim, victim_child, other.clone()], &reorg);
+        assert_eq!(txids(&branch), txids(&[other, double_spend]));
+    }
+}
@@ -0,0 +1,73 @@
+// RPC Connection
+// Lets the player reach the node of the running level from another terminal
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use bitcoind::{
+    bitcoincore_rpc::{Auth, Client, RpcApi},
+    BitcoinD,
+};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Everything needed to talk to a running level node
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RpcConnection {
+    pub url: String,
+    /// url of the player wallet, wallet calls are routed to it
+    pub wallet_url: String,
+    pub cookie_file: PathBuf,
+}
+
+impl RpcConnection {
+    pub fn new(bitcoind: &BitcoinD, wallet: &str) -> Self {
+        Self {
+            url: bitcoind.rpc_url(),
+            wallet_url: bitcoind.rpc_url_with_wallet(wallet),
+            cookie_file: bitcoind.params.cookie_file.clone(),
+        }
+    }
+
+    /// Client for node wide calls
+    pub fn node_client(&self) -> Result<Client> {
+        Ok(Client::new(
+            &self.url,
+            Auth::CookieFile(self.cookie_file.clone()),
+        )?)
+    }
+
+    /// Client for the player wallet
+    pub fn wallet_client(&self) -> Result<Client> {
+        Ok(Client::new(
+            &self.wallet_url,
+            Auth::CookieFile(self.cookie_file.clone()),
+        )?)
+    }
+
+    /// Calls `method` on the player wallet endpoint.
+    /// Params are parsed as JSON when possible and passed as strings otherwise.
+    pub fn call(&self, method: &str, params: &[String]) -> Result<Value> {
+        let client = self.wallet_client()?;
+        let params: Vec<Value> = params
+            .iter()
+            .map(|param| {
+                serde_json::from_str(param).unwrap_or_else(|_| Value::String(param.clone()))
+            })
+            .collect();
+        Ok(client.call(method, &params)?)
+    }
+
+    pub fn print_details(&self) {
+        println!("\n{}", "Node Connection:".cyan().bold());
+        println!("{} {}", "RPC URL:".cyan(), self.url);
+        println!("{} {}", "Wallet URL:".cyan(), self.wallet_url);
+        println!("{} {}", "Cookie file:".cyan(), self.cookie_file.display());
+        println!(
+            "{} {}",
+            "Try:".cyan(),
+            "btc-ctf rpc getrawmempool".bright_magenta()
+        );
+    }
+}
@@ -0,0 +1,224 @@
+// Session Registry
+// Every level node runs in its own session directory under bin/bitcoin/sessions,
+// so several games can run side by side on the same machine.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoind::{
+    bitcoincore_rpc::{Auth, Client, RpcApi},
+    BitcoinD,
+};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use super::RpcConnection;
+use crate::constrants::SESSIONS_DIR;
+
+const SESSION_FILE: &str = "session.toml";
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// written by bitcoind into its network data directory
+const PID_FILE: &str = "regtest/bitcoind.pid";
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// pid of the btc-ctf process owning the node
+    pub owner_pid: u32,
+    /// unix timestamp (in seconds) the session was started at
+    pub started_at: u64,
+    pub datadir: PathBuf,
+    pub rpc: RpcConnection,
+    /// the other nodes of the level network, living under `datadir`
+    #[serde(default)]
+    pub peers: Vec<PeerNode>,
+}
+
+/// A peer node of the level network, enough to stop it from another process
+#[derive(Serialize, Deserialize)]
+pub struct PeerNode {
+    pub pid: u32,
+    pub rpc_port: u16,
+    pub cookie_file: PathBuf,
+}
+
+impl PeerNode {
+    pub fn new(bitcoind: &BitcoinD) -> Result<Self> {
+        let pid_file = bitcoind.workdir().join(PID_FILE);
+        let pid = fs::read_to_string(&pid_file)
+            .with_context(|| format!("Failed to read {}", pid_file.display()))?
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid pid in {}", pid_file.display()))?;
+        Ok(Self {
+            pid,
+            rpc_port: bitcoind.params.rpc_socket.port(),
+            cookie_file: bitcoind.params.cookie_file.clone(),
+        })
+    }
+
+    fn client(&self) -> Result<Client> {
+        Ok(Client::new(
+            &format!("http://127.0.0.1:{}", self.rpc_port),
+            Auth::CookieFile(self.cookie_file.clone()),
+        )?)
+    }
+}
+
+impl Session {
+    /// Picks a fresh session id and data directory
+    pub fn reserve() -> (String, PathBuf) {
+        loop {
+            let id = format!("{:08x}", rand::random::<u32>());
+            let datadir = Path::new(SESSIONS_DIR).join(&id);
+            if !datadir.exists() {
+                return (id, datadir);
+            }
+        }
+    }
+
+    pub fn new(id: String, datadir: PathBuf, rpc: RpcConnection) -> Result<Self> {
+        Ok(Self {
+            id,
+            owner_pid: std::process::id(),
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            datadir,
+            rpc,
+            peers: Vec::new(),
+        })
+    }
+
+    /// Registers the session by writing it into its data directory
+    pub fn save(&self) -> Result<()> {
+        let path = self.datadir.join(SESSION_FILE);
+        let toml_string = toml::to_string_pretty(self).context("Failed to serialize session")?;
+        fs::write(&path, toml_string)
+            .with_context(|| format!("Failed to write session file: {}", path.display()))
+    }
+
+    /// Lists every registered session, oldest first, skipping unreadable session files
+    pub fn list() -> Result<Vec<Self>> {
+        let dir = Path::new(SESSIONS_DIR);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut sessions: Vec<Self> = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path().join(SESSION_FILE);
+            // node still starting up or a directory we do not own
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+            // a broken file must not hide every other session
+            match toml::from_str(&contents) {
+                Ok(session) => sessions.push(session),
+                Err(err) => println!(
+                    "{}",
+                    format!("Skipping broken session file {}: {err}", path.display()).yellow()
+                ),
+            }
+        }
+        sessions.sort_by_key(|session| session.started_at);
+        Ok(sessions)
+    }
+
+    /// Finds a session by id, or the most recent live session if no id is given
+    pub fn find(id: Option<&str>) -> Result<Self> {
+        let sessions = Self::list()?;
+        match id {
+            Some(id) => sessions
+                .into_iter()
+                .find(|session| session.id == id)
+                .ok_or_else(|| anyhow!("No session with id {id}. See `btc-ctf sessions`.")),
+            None => sessions
+                .into_iter()
+                .rev()
+                .find(|session| !session.is_orphaned())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "No level is running. Start one with `btc-ctf new` or `btc-ctf continue`."
+                    )
+                }),
+        }
+    }
+
+    /// A session is orphaned once the btc-ctf process that started it is gone
+    pub fn is_orphaned(&self) -> bool {
+        !process_alive(self.owner_pid)
+    }
+
+    /// Stops the session nodes (if still running) and removes its data directory
+    pub fn kill(&self) -> Result<()> {
+        for peer in &self.peers {
+            if let Ok(client) = peer.client() {
+                if client.stop().is_ok() {
+                    // the pid tells when the peer released its data directory
+                    wait_until(|| !process_alive(peer.pid)).with_context(|| {
+                        format!("Peer {} of session {} did not stop", peer.pid, self.id)
+                    })?;
+                }
+            }
+        }
+        if let Ok(client) = self.rpc.node_client() {
+            if client.stop().is_ok() {
+                // wait for the node to release its data directory
+                wait_until(|| client.get_block_count().is_err())
+                    .with_context(|| format!("Node of session {} did not stop", self.id))?;
+            }
+        }
+        if self.datadir.exists() {
+            fs::remove_dir_all(&self.datadir).with_context(|| {
+                format!("Failed to remove session data: {}", self.datadir.display())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks the system whether `pid` is running, with tools every supported platform ships
+fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "windows") {
+        // without a match tasklist still succeeds, printing an info line instead of a row
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+            .output()
+            .is_ok_and(|output| {
+                String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\""))
+            })
+    } else {
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+}
+
+/// Polls `stopped` until it holds or `STOP_TIMEOUT` runs out
+fn wait_until(mut stopped: impl FnMut() -> bool) -> Result<()> {
+    let started = SystemTime::now();
+    while !stopped() {
+        if started.elapsed()? > STOP_TIMEOUT {
+            bail!("Timed out after {} seconds", STOP_TIMEOUT.as_secs());
+        }
+        thread::sleep(STOP_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_process_is_alive() {
+        assert!(process_alive(std::process::id()));
+    }
+}
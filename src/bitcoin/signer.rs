@@ -0,0 +1,303 @@
+// Signers
+// Every signature of the CTF goes through a `Signer`. Honest wallets sign with `HonestSigner`,
+// broken ones pick their nonces through a `NonceSource` (see weak_nonce.rs): their signatures
+// verify like any other one, but leak the key to whoever spots how the nonces were picked.
+
+use anyhow::{Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    key::{Keypair, Parity, Secp256k1, XOnlyPublicKey},
+    secp256k1::{
+        constants::CURVE_ORDER, ecdsa, schnorr, All, Message, PublicKey, Scalar, SecretKey,
+    },
+};
+use rand::Rng;
+
+/// Signs messages for the keys it is given
+pub trait Signer {
+    /// BIP340 signature of `msg` by `keypair`
+    fn sign_schnorr(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        keypair: &Keypair,
+    ) -> Result<schnorr::Signature>;
+
+    /// Low-s ECDSA signature of `msg` by `secret_key`
+    fn sign_ecdsa(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        secret_key: &SecretKey,
+    ) -> Result<ecdsa::Signature>;
+}
+
+/// Signs like a sound wallet: BIP340 nonces for Schnorr and RFC6979 ones for ECDSA,
+/// both with extra randomness drawn from `rng`
+pub struct HonestSigner<R> {
+    pub rng: R,
+}
+
+impl<R: Rng> Signer for HonestSigner<R> {
+    fn sign_schnorr(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        keypair: &Keypair,
+    ) -> Result<schnorr::Signature> {
+        Ok(secp.sign_schnorr_with_aux_rand(msg, keypair, &self.rng.gen()))
+    }
+
+    fn sign_ecdsa(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        secret_key: &SecretKey,
+    ) -> Result<ecdsa::Signature> {
+        Ok(secp.sign_ecdsa_with_noncedata(msg, secret_key, &self.rng.gen()))
+    }
+}
+
+/// How a broken wallet picks the nonce k of its next signature
+pub trait NonceSource {
+    fn next_nonce(&mut self, msg: &Message) -> Result<SecretKey>;
+}
+
+/// Any nonce source is a signer, with the textbook schemes around the picked nonce
+impl<T: NonceSource> Signer for T {
+    fn sign_schnorr(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        keypair: &Keypair,
+    ) -> Result<schnorr::Signature> {
+        let nonce = self.next_nonce(msg)?;
+        sign_schnorr_with_nonce(secp, msg, keypair, nonce)
+    }
+
+    fn sign_ecdsa(
+        &mut self,
+        secp: &Secp256k1<All>,
+        msg: &Message,
+        secret_key: &SecretKey,
+    ) -> Result<ecdsa::Signature> {
+        let nonce = self.next_nonce(msg)?;
+        sign_ecdsa_with_nonce(secp, msg, secret_key, nonce)
+    }
+}
+
+/// Signs `msg` for `keypair` following BIP340, with `k` as the nonce
+#[allow(clippy::many_single_char_names)] // names follow BIP340
+pub fn sign_schnorr_with_nonce(
+    secp: &Secp256k1<All>,
+    msg: &Message,
+    keypair: &Keypair,
+    mut k: SecretKey,
+) -> Result<schnorr::Signature> {
+    // BIP340 only uses points with an even y, the nonce and key are negated otherwise
+    let (r, parity) = k.x_only_public_key(secp);
+    if parity == Parity::Odd {
+        k = k.negate();
+    }
+    let (public_key, parity) = keypair.x_only_public_key();
+    let mut x = keypair.secret_key();
+    if parity == Parity::Odd {
+        x = x.negate();
+    }
+
+    // s = k + e * x
+    let e = schnorr_challenge(&r, &public_key, msg.as_ref());
+    let s = x.mul_tweak(&e)?.add_tweak(&Scalar::from(k))?;
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&r.serialize());
+    signature[32..].copy_from_slice(&s.secret_bytes());
+    Ok(schnorr::Signature::from_slice(&signature)?)
+}
+
+/// Signs `msg` for `secret_key` following ECDSA, with `k` as the nonce.
+/// s gets normalized to the lower half of the curve order, as standardness requires.
+#[allow(clippy::many_single_char_names)] // names follow SEC1
+pub fn sign_ecdsa_with_nonce(
+    secp: &Secp256k1<All>,
+    msg: &Message,
+    secret_key: &SecretKey,
+    k: SecretKey,
+) -> Result<ecdsa::Signature> {
+    // r = (k * G).x mod n, an x above the curve order is astronomically unlikely
+    let point = PublicKey::from_secret_key(secp, &k).serialize();
+    let r = SecretKey::from_slice(&point[1..]).context("Nonce point is above the curve order")?;
+
+    // s = (z + r * x) / k
+    let z = Scalar::from_be_bytes(*msg.as_ref())?;
+    let s = secret_key
+        .mul_tweak(&Scalar::from(r))?
+        .add_tweak(&z)?
+        .mul_tweak(&Scalar::from(invert(k)?))?;
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&r.secret_bytes());
+    signature[32..].copy_from_slice(&s.secret_bytes());
+    let mut signature = ecdsa::Signature::from_compact(&signature)?;
+    signature.normalize_s();
+    Ok(signature)
+}
+
+/// `e = hash_BIP0340/challenge(r || P || m) mod n`
+pub fn schnorr_challenge(
+    r: &XOnlyPublicKey,
+    public_key: &XOnlyPublicKey,
+    msg: &[u8; 32],
+) -> Scalar {
+    let tag = sha256::Hash::hash(b"BIP0340/challenge");
+
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(&r.serialize());
+    engine.input(&public_key.serialize());
+    engine.input(msg);
+    reduce(sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+/// `bytes mod n`, n is above 2^255 so a single subtraction is enough
+fn reduce(mut bytes: [u8; 32]) -> Scalar {
+    // big endian arrays of the same length compare like the numbers they hold
+    if bytes >= CURVE_ORDER {
+        let mut borrow = false;
+        for (byte, order) in bytes.iter_mut().zip(CURVE_ORDER).rev() {
+            let (difference, under) = byte.overflowing_sub(order);
+            let (difference, borrowed) = difference.overflowing_sub(u8::from(borrow));
+            *byte = difference;
+            borrow = under || borrowed;
+        }
+    }
+    Scalar::from_be_bytes(bytes).expect("reduced below the curve order")
+}
+
+/// a^-1 = a^(n-2) mod n
+pub fn invert(a: SecretKey) -> Result<SecretKey> {
+    // n ends with 0x41, subtracting 2 never borrows
+    let mut exponent = CURVE_ORDER;
+    exponent[31] -= 2;
+
+    let mut result: Option<SecretKey> = None;
+    for byte in exponent {
+        for bit in (0..8).rev() {
+            if let Some(squared) = result {
+                result = Some(squared.mul_tweak(&Scalar::from(squared))?);
+            }
+            if (byte >> bit) & 1 == 1 {
+                result = Some(match result {
+                    Some(partial) => partial.mul_tweak(&Scalar::from(a))?,
+                    None => a,
+                });
+            }
+        }
+    }
+    result.context("Exponent is not zero")
+}
+
+/// The scalar `value`, for nonces and steps given as plain numbers
+pub fn scalar_from_u64(value: u64) -> Scalar {
+    let mut bytes = [0u8; 32];
+    bytes[24..].copy_from_slice(&value.to_be_bytes());
+    Scalar::from_be_bytes(bytes).expect("a u64 is below the curve order")
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    fn nonce(value: u64) -> SecretKey {
+        SecretKey::from_slice(&scalar_from_u64(value).to_be_bytes()).unwrap()
+    }
+
+    #[test]
+    fn schnorr_with_chosen_nonces_verifies() {
+        let secp = Secp256k1::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        // keys and nonces of both parities
+        for idx in 0..16 {
+            let keypair = Keypair::new(&secp, &mut rng);
+            let msg = Message::from_digest([idx; 32]);
+            let k = nonce(u64::from(idx) + 1);
+            let signature = sign_schnorr_with_nonce(&secp, &msg, &keypair, k).unwrap();
+            secp.verify_schnorr(&signature, &msg, &keypair.x_only_public_key().0)
+                .unwrap();
+            assert_eq!(
+                signature.serialize()[..32],
+                k.x_only_public_key(&secp).0.serialize()
+            );
+        }
+    }
+
+    #[test]
+    fn ecdsa_with_chosen_nonces_verifies() {
+        let secp = Secp256k1::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        for idx in 0..16 {
+            let key = SecretKey::new(&mut rng);
+            let msg = Message::from_digest([idx + 1; 32]);
+            let k = SecretKey::new(&mut rng);
+            let signature = sign_ecdsa_with_nonce(&secp, &msg, &key, k).unwrap();
+            // verification also rejects signatures with a high s
+            secp.verify_ecdsa(&msg, &signature, &key.public_key(&secp))
+                .unwrap();
+            assert_eq!(
+                signature.serialize_compact()[..32],
+                PublicKey::from_secret_key(&secp, &k).serialize()[1..]
+            );
+        }
+    }
+
+    #[test]
+    fn honest_signatures_verify() {
+        let secp = Secp256k1::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let keypair = Keypair::new(&secp, &mut rng);
+        let msg = Message::from_digest([1; 32]);
+        let mut signer = HonestSigner { rng };
+        let signature = signer.sign_schnorr(&secp, &msg, &keypair).unwrap();
+        secp.verify_schnorr(&signature, &msg, &keypair.x_only_public_key().0)
+            .unwrap();
+        let signature = signer
+            .sign_ecdsa(&secp, &msg, &keypair.secret_key())
+            .unwrap();
+        secp.verify_ecdsa(&msg, &signature, &keypair.public_key())
+            .unwrap();
+    }
+
+    #[test]
+    fn inverse_times_value_is_one() {
+        let one = nonce(1);
+        let mut rng = ChaCha20Rng::seed_from_u64(4);
+        let values = [nonce(1), nonce(2), one.negate(), SecretKey::new(&mut rng)];
+        for a in values {
+            let product = a.mul_tweak(&Scalar::from(invert(a).unwrap())).unwrap();
+            assert_eq!(product, one);
+        }
+    }
+
+    #[test]
+    fn scalars_from_numbers_are_big_endian() {
+        assert_eq!(scalar_from_u64(0x0102).to_be_bytes()[30..], [1, 2]);
+    }
+
+    #[test]
+    fn challenges_are_reduced_mod_n() {
+        assert_eq!(reduce([0; 32]), Scalar::ZERO);
+        assert_eq!(reduce(CURVE_ORDER), Scalar::ZERO);
+        // 2^256 - 1 - n is the bitwise complement of n
+        assert_eq!(
+            reduce([0xff; 32]).to_be_bytes(),
+            CURVE_ORDER.map(|byte| !byte)
+        );
+        let mut below = CURVE_ORDER;
+        below[31] -= 1;
+        assert_eq!(reduce(below).to_be_bytes(), below);
+    }
+}
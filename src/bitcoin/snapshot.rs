@@ -0,0 +1,134 @@
+// Chain Snapshots
+// The funding work a level needs (mining blocks, maturing coinbases) is done once per
+// chain template and cached as the data directory of a stopped node.
+// New sessions start from a copy of it, so retries start instantly.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash},
+    key::{Keypair, Secp256k1},
+    secp256k1::All,
+    taproot::TaprootSpendInfo,
+    Address, Network,
+};
+use bitcoind::{bitcoincore_rpc::RpcApi, BitcoinD, Conf};
+use colored::Colorize;
+use serde::Serialize;
+
+use super::BitcoindBinary;
+use crate::constrants::SNAPSHOTS_DIR;
+
+/// Bumped whenever the content of a snapshot changes for the same template,
+/// e.g. 2 mines the coinbases to the template funding key
+const SNAPSHOT_FORMAT: u32 = 2;
+/// Files tied to a running node which must not be carried over to a new session
+const VOLATILE_FILES: &[&str] = &[
+    ".cookie",
+    ".lock",
+    ".walletlock",
+    "bitcoind.pid",
+    "debug.log",
+];
+
+/// Chain state a level starts from
+#[derive(Serialize)]
+pub struct ChainTemplate {
+    /// names the snapshot, usually the level id
+    pub name: &'static str,
+    /// blocks mined to the funding key of the template
+    pub blocks: u64,
+}
+
+impl ChainTemplate {
+    /// Key the template mines its coins to. It is derived from the template name so the
+    /// coins, and the transactions spending them, are the same on every machine.
+    pub fn funding_key(&self, secp: &Secp256k1<All>) -> Keypair {
+        let secret = sha256::Hash::hash(format!("btc-ctf/{}/funding", self.name).as_bytes());
+        Keypair::from_seckey_slice(secp, secret.as_byte_array()).expect("hash is a valid key")
+    }
+
+    /// Copies the chain of the template into `datadir`,
+    /// building the snapshot first if it is not cached yet.
+    pub fn restore(&self, binary: &BitcoindBinary, datadir: &Path) -> Result<()> {
+        let snapshot = self.snapshot_dir(binary)?;
+        if !snapshot.exists() {
+            println!(
+                "{}",
+                format!(
+                    "Building the {} chain, this is only done once...",
+                    self.name
+                )
+                .bright_black()
+            );
+            self.build(binary, &snapshot)?;
+        }
+        copy_dir(&snapshot, datadir)
+            .with_context(|| format!("Failed to copy chain snapshot: {}", snapshot.display()))
+    }
+
+    /// Snapshots are keyed by a hash of the template, of the bitcoind major version
+    /// and of the snapshot format, so stale snapshots are never reused
+    fn snapshot_dir(&self, binary: &BitcoindBinary) -> Result<PathBuf> {
+        let (major, _) = binary.version()?;
+        let params = serde_json::to_vec(&(self, major, SNAPSHOT_FORMAT))?;
+        let hash = sha256::Hash::hash(&params).to_string();
+        Ok(Path::new(SNAPSHOTS_DIR).join(format!("{}-{}", self.name, &hash[..16])))
+    }
+
+    /// Runs the template on a fresh node and keeps its data directory once the node stopped
+    fn build(&self, binary: &BitcoindBinary, snapshot: &Path) -> Result<()> {
+        // built aside and moved in place once complete, a half built snapshot is never used
+        let building = snapshot.with_extension(format!("building-{:08x}", rand::random::<u32>()));
+        let built = self.run(binary, &building).and_then(|()| {
+            match fs::rename(&building, snapshot) {
+                // another game built the same snapshot in the meantime
+                Err(_) if snapshot.exists() => Ok(fs::remove_dir_all(&building)?),
+                renamed => renamed.context("Failed to save chain snapshot"),
+            }
+        });
+        if built.is_err() && building.exists() {
+            let _ = fs::remove_dir_all(&building);
+        }
+        built
+    }
+
+    fn run(&self, binary: &BitcoindBinary, datadir: &Path) -> Result<()> {
+        let mut conf = Conf::default();
+        conf.staticdir = Some(datadir.to_path_buf());
+        let mut bitcoind = BitcoinD::with_conf(&binary.path, &conf)
+            .with_context(|| format!("Failed to start {}", binary.path.display()))?;
+
+        let secp = Secp256k1::new();
+        let funding_key = self.funding_key(&secp).x_only_public_key().0;
+        let spend_info = TaprootSpendInfo::new_key_spend(&secp, funding_key, None);
+        let address = Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest);
+        let mined = bitcoind.client.generate_to_address(self.blocks, &address);
+
+        // stopping flushes the chain state and wallet to disk
+        bitcoind.stop()?;
+        mined?;
+        Ok(())
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if VOLATILE_FILES.iter().any(|volatile| name == *volatile) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(name))?;
+        } else {
+            fs::copy(entry.path(), to.join(name))?;
+        }
+    }
+    Ok(())
+}
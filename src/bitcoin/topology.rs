@@ -0,0 +1,187 @@
+// Level Networks
+// Levels about propagation, reorgs, eclipse attacks or double-spends across peers run several nodes.
+// A topology declares the nodes and how they are wired, the helpers below rewire them while a level runs.
+
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use bitcoind::{
+    bitcoincore_rpc::{Auth, Client, RpcApi},
+    BitcoinD,
+};
+use serde::Deserialize;
+
+use super::CtfFramework;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a node does in the level network
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    /// produces the blocks of the level
+    Miner,
+    /// only relays transactions and blocks
+    Relay,
+}
+
+/// Nodes of a level network and how they are connected
+#[derive(Clone, Copy)]
+pub struct Topology {
+    /// role of every node, node 0 being the one the player talks to
+    pub roles: &'static [NodeRole],
+    /// nodes connected to each other, nodes in different groups are partitioned
+    /// and nodes left out of every group are isolated. Empty connects every node.
+    pub groups: &'static [&'static [usize]],
+}
+
+impl Topology {
+    /// A single mining node
+    pub const SINGLE: Self = Self {
+        roles: &[NodeRole::Miner],
+        groups: &[],
+    };
+
+    pub const fn node_count(&self) -> usize {
+        self.roles.len()
+    }
+}
+
+#[derive(Deserialize)]
+struct PeerInfo {
+    id: u32,
+    addr: String,
+}
+
+#[allow(dead_code)]
+impl CtfFramework {
+    /// Node `idx` of the level network, node 0 being the one the player talks to
+    pub fn node(&self, idx: usize) -> Result<&BitcoinD> {
+        match idx {
+            0 => Ok(&self.bitcoind),
+            idx => self
+                .peers
+                .get(idx - 1)
+                .with_context(|| format!("No node {idx} in the level network")),
+        }
+    }
+
+    pub fn role(&self, idx: usize) -> Option<NodeRole> {
+        self.topology.roles.get(idx).copied()
+    }
+
+    /// A new RPC client for node `idx`, for tasks which need a client of their own
+    pub fn node_client(&self, idx: usize) -> Result<Client> {
+        let node = self.node(idx)?;
+        Ok(Client::new(
+            &node.rpc_url(),
+            Auth::CookieFile(node.params.cookie_file.clone()),
+        )?)
+    }
+
+    /// Opens a connection from node `from` to node `to` and waits until it is up
+    pub async fn connect(&self, from: usize, to: usize) -> Result<()> {
+        let addr = self.p2p_addr(to)?;
+        let client = &self.node(from)?.client;
+        client.onetry_node(&addr)?;
+
+        let mut waited = Duration::ZERO;
+        while !self.peers_of(from)?.iter().any(|peer| peer.addr == addr) {
+            if waited > CONNECT_TIMEOUT {
+                bail!("Node {from} could not connect to node {to}");
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            waited += POLL_INTERVAL;
+        }
+        Ok(())
+    }
+
+    /// Drops every connection between nodes `a` and `b`, whichever side opened it
+    pub fn disconnect(&self, a: usize, b: usize) -> Result<()> {
+        for (from, to) in [(a, b), (b, a)] {
+            let addr = self.p2p_addr(to)?;
+            for peer in self.peers_of(from)? {
+                if peer.addr == addr {
+                    self.node(from)?.client.disconnect_node_by_id(peer.id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_connected(&self, a: usize, b: usize) -> Result<bool> {
+        for (from, to) in [(a, b), (b, a)] {
+            let addr = self.p2p_addr(to)?;
+            if self.peers_of(from)?.iter().any(|peer| peer.addr == addr) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Splits the network: nodes in the same group get connected to each other,
+    /// nodes in different groups (or in none) get disconnected.
+    pub async fn partition(&self, groups: &[&[usize]]) -> Result<()> {
+        let group_of = |node: usize| groups.iter().position(|group| group.contains(&node));
+        for a in 0..self.topology.node_count() {
+            for b in (a + 1)..self.topology.node_count() {
+                let same_group = group_of(a).is_some() && group_of(a) == group_of(b);
+                match (same_group, self.is_connected(a, b)?) {
+                    (true, false) => self.connect(a, b).await?,
+                    (false, true) => self.disconnect(a, b)?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reconnects every node to every other
+    pub async fn heal(&self) -> Result<()> {
+        let all: Vec<usize> = (0..self.topology.node_count()).collect();
+        self.partition(&[&all]).await
+    }
+
+    /// Waits until `nodes` agree on the best block and the mempool
+    pub async fn wait_for_sync(&self, nodes: &[usize], timeout: Duration) -> Result<()> {
+        let mut waited = Duration::ZERO;
+        while !self.in_sync(nodes)? {
+            if waited > timeout {
+                bail!(
+                    "Nodes {nodes:?} did not sync within {} seconds",
+                    timeout.as_secs()
+                );
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            waited += POLL_INTERVAL;
+        }
+        Ok(())
+    }
+
+    fn in_sync(&self, nodes: &[usize]) -> Result<bool> {
+        let mut tips = HashSet::new();
+        let mut mempools = HashSet::new();
+        for &idx in nodes {
+            let client = &self.node(idx)?.client;
+            tips.insert(client.get_best_block_hash()?);
+            let mut mempool = client.get_raw_mempool()?;
+            mempool.sort();
+            mempools.insert(mempool);
+        }
+        Ok(tips.len() <= 1 && mempools.len() <= 1)
+    }
+
+    fn p2p_addr(&self, idx: usize) -> Result<String> {
+        let socket = self
+            .node(idx)?
+            .params
+            .p2p_socket
+            .with_context(|| format!("Node {idx} does not accept p2p connections"))?;
+        Ok(socket.to_string())
+    }
+
+    fn peers_of(&self, idx: usize) -> Result<Vec<PeerInfo>> {
+        Ok(self.node(idx)?.client.call("getpeerinfo", &[])?)
+    }
+}
@@ -1,17 +1,27 @@
 // Transaction Builder
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use bitcoin::{
     absolute::LockTime,
+    ecdsa,
     hashes::Hash,
-    key::{Keypair, Secp256k1},
-    secp256k1::{All, Message, Scalar, SecretKey},
+    key::{Keypair, Secp256k1, XOnlyPublicKey},
+    opcodes::{all::OP_CHECKMULTISIG, OP_0},
+    script::{Builder, PushBytes},
+    secp256k1::{All, Message, PublicKey, Scalar, SecretKey},
     sighash::{Prevouts, SighashCache},
+    taproot::{self, ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo},
     transaction::Version,
-    Address, Amount, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Txid,
-    Witness,
+    Address, Amount, EcdsaSighashType, OutPoint, Script, ScriptBuf, Sequence, TapSighash,
+    TapSighashType, Transaction, TxIn, TxOut, Txid, Witness,
 };
-use rand::Rng;
+
+use super::Signer;
+
+/// Most keys `OP_CHECKMULTISIG` accepts, the limit of P2WSH multisig scripts
+const MAX_MULTISIG_KEYS: usize = 20;
+/// Most keys of a standard P2SH multisig, bound by the size of its redeem script
+const MAX_P2SH_MULTISIG_KEYS: usize = 15;
 
 /// A builder for constructing Bitcoin transactions.
 pub struct TransactionBuilder {
@@ -85,13 +95,14 @@ impl TransactionBuilder {
 ///
 /// This function creates a signature for the specified input of a transaction,
 /// optionally applying a tweak to the private key before signing.
-/// Note : Uses Single Byte Nonce.
+/// Note : Signs through `signer`, a flawed one leaks the key to whoever spots its nonces.
 pub fn add_signature(
     transaction: &mut Transaction,
     input_idx: usize,
     prevouts: &[&TxOut],
     private_key: SecretKey,
     tweak: Option<&Scalar>,
+    signer: &mut impl Signer,
     secp: &Secp256k1<All>,
 ) -> Result<()> {
     // apply tweak if provided
@@ -101,7 +112,7 @@ pub fn add_signature(
     };
 
     // Compute the sighash
-    let mut sighash_cache = SighashCache::new(transaction.clone());
+    let mut sighash_cache = SighashCache::new(&*transaction);
     let sighash = sighash_cache.taproot_key_spend_signature_hash(
         input_idx,
         &Prevouts::All(prevouts),
@@ -111,9 +122,7 @@ pub fn add_signature(
     // Create and sign the message
     let message = Message::from_digest(sighash.as_raw_hash().to_byte_array());
 
-    let mut aux_rand = [0u8; 32];
-    aux_rand[31] = rand::thread_rng().gen();
-    let signature = secp.sign_schnorr_with_aux_rand(&message, &keypair, &aux_rand);
+    let signature = signer.sign_schnorr(secp, &message, &keypair)?;
 
     // Verify the signature
     secp.verify_schnorr(&signature, &message, &keypair.x_only_public_key().0)?;
@@ -125,3 +134,709 @@ pub fn add_signature(
 
     Ok(())
 }
+
+/// `m` of `keys` multisig script: `OP_m <keys> OP_n OP_CHECKMULTISIG`,
+/// the redeem script of a P2SH multisig (up to 15 keys) or the witness script
+/// of a P2WSH one (up to 20 keys)
+#[allow(dead_code)]
+pub fn multisig_script(required: usize, keys: &[PublicKey]) -> Result<ScriptBuf> {
+    if required == 0 || required > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+        bail!("Can't build a {required} of {} multisig", keys.len());
+    }
+    let mut builder = Builder::new().push_int(i64::try_from(required)?);
+    for key in keys {
+        builder = builder.push_key(&bitcoin::PublicKey::new(*key));
+    }
+    Ok(builder
+        .push_int(i64::try_from(keys.len())?)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script())
+}
+
+/// ECDSA signature of an input with the legacy sighash algorithm, committing to `script_code`.
+/// The scriptSig is not signed and ECDSA signatures can be re-encoded (high/low `s`, loose DER),
+/// which is what makes legacy txids malleable. The sighash does not commit to the input amounts
+/// either, so a signer can't verify the fee, and `SIGHASH_SINGLE` without a matching output
+/// signs the number 1.
+pub fn sign_legacy(
+    transaction: &Transaction,
+    input_idx: usize,
+    script_code: &Script,
+    private_key: SecretKey,
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<ecdsa::Signature> {
+    let sighash_cache = SighashCache::new(transaction);
+    let sighash =
+        sighash_cache.legacy_signature_hash(input_idx, script_code, sighash_type.to_u32())?;
+    let message = Message::from_digest(sighash.to_byte_array());
+    sign_ecdsa(&message, private_key, sighash_type, signer, secp)
+}
+
+/// ECDSA signature of an input with the BIP143 sighash algorithm, committing to
+/// `witness_script` and the `value` of the spent output
+#[allow(clippy::too_many_arguments)]
+pub fn sign_p2wsh(
+    transaction: &Transaction,
+    input_idx: usize,
+    witness_script: &Script,
+    value: Amount,
+    private_key: SecretKey,
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<ecdsa::Signature> {
+    let mut sighash_cache = SighashCache::new(transaction);
+    let sighash =
+        sighash_cache.p2wsh_signature_hash(input_idx, witness_script, value, sighash_type)?;
+    let message = Message::from_digest(sighash.to_byte_array());
+    sign_ecdsa(&message, private_key, sighash_type, signer, secp)
+}
+
+/// Spends a P2PKH output: `script_sig` = `<sig> <pubkey>`
+#[allow(dead_code)]
+pub fn add_p2pkh_signature(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    prevout: &TxOut,
+    private_key: SecretKey,
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<()> {
+    let signature = sign_legacy(
+        transaction,
+        input_idx,
+        &prevout.script_pubkey,
+        private_key,
+        sighash_type,
+        signer,
+        secp,
+    )?;
+    let public_key = bitcoin::PublicKey::new(private_key.public_key(secp));
+
+    transaction.input[input_idx].script_sig = Builder::new()
+        .push_slice(signature.serialize())
+        .push_key(&public_key)
+        .into_script();
+    Ok(())
+}
+
+/// Spends a P2SH multisig output: `script_sig` = `OP_0 <sigs> <redeem_script>`.
+/// Takes exactly `m` keys, in any order, signatures follow the order of the redeem script.
+#[allow(dead_code)]
+pub fn add_p2sh_multisig_signatures(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    redeem_script: &Script,
+    private_keys: &[SecretKey],
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<()> {
+    // OP_CHECKMULTISIG pops one element too many
+    let mut builder = Builder::new().push_opcode(OP_0);
+    let private_keys = in_script_order(redeem_script, private_keys, MAX_P2SH_MULTISIG_KEYS, secp)?;
+    for private_key in private_keys {
+        let signature = sign_legacy(
+            transaction,
+            input_idx,
+            redeem_script,
+            private_key,
+            sighash_type,
+            signer,
+            secp,
+        )?;
+        builder = builder.push_slice(signature.serialize());
+    }
+
+    transaction.input[input_idx].script_sig = builder
+        .push_slice(<&PushBytes>::try_from(redeem_script.as_bytes())?)
+        .into_script();
+    Ok(())
+}
+
+/// Spends a P2WPKH output: witness = `<sig> <pubkey>`, with an empty `script_sig`
+#[allow(dead_code)]
+pub fn add_p2wpkh_signature(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    prevout: &TxOut,
+    private_key: SecretKey,
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<()> {
+    let mut sighash_cache = SighashCache::new(&*transaction);
+    let sighash = sighash_cache.p2wpkh_signature_hash(
+        input_idx,
+        &prevout.script_pubkey,
+        prevout.value,
+        sighash_type,
+    )?;
+    let message = Message::from_digest(sighash.to_byte_array());
+    let signature = sign_ecdsa(&message, private_key, sighash_type, signer, secp)?;
+    let public_key = bitcoin::PublicKey::new(private_key.public_key(secp));
+
+    transaction.input[input_idx].witness = Witness::p2wpkh(&signature, &public_key.inner);
+    Ok(())
+}
+
+/// Spends a P2WSH output with `items` satisfying `witness_script`:
+/// witness = `<items> <witness_script>`, e.g. the signatures of [`sign_p2wsh`] and puzzle answers
+pub fn set_p2wsh_witness(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    items: &[Vec<u8>],
+    witness_script: &Script,
+) {
+    let mut witness = Witness::from_slice(items);
+    witness.push(witness_script.as_bytes());
+    transaction.input[input_idx].witness = witness;
+}
+
+/// Spends a P2WSH multisig output: witness = `<empty> <sigs> <witness_script>`.
+/// Takes exactly `m` keys, in any order, signatures follow the order of the witness script.
+#[allow(dead_code, clippy::too_many_arguments)]
+pub fn add_p2wsh_multisig_signatures(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    witness_script: &Script,
+    value: Amount,
+    private_keys: &[SecretKey],
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<()> {
+    // OP_CHECKMULTISIG pops one element too many
+    let mut items = vec![Vec::new()];
+    let private_keys = in_script_order(witness_script, private_keys, MAX_MULTISIG_KEYS, secp)?;
+    for private_key in private_keys {
+        let signature = sign_p2wsh(
+            transaction,
+            input_idx,
+            witness_script,
+            value,
+            private_key,
+            sighash_type,
+            signer,
+            secp,
+        )?;
+        items.push(signature.to_vec());
+    }
+
+    set_p2wsh_witness(transaction, input_idx, &items, witness_script);
+    Ok(())
+}
+
+/// Signs the sighash `message` and checks the signature before it goes into a transaction
+fn sign_ecdsa(
+    message: &Message,
+    private_key: SecretKey,
+    sighash_type: EcdsaSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<ecdsa::Signature> {
+    let signature = signer.sign_ecdsa(secp, message, &private_key)?;
+    secp.verify_ecdsa(message, &signature, &private_key.public_key(secp))?;
+    Ok(ecdsa::Signature {
+        signature,
+        sighash_type,
+    })
+}
+
+/// Threshold and keys of a `OP_m <keys> OP_n OP_CHECKMULTISIG` script
+fn parse_multisig(script: &Script) -> Result<(usize, Vec<PublicKey>)> {
+    let instructions = script.instructions().collect::<Result<Vec<_>, _>>()?;
+    let [first, keys @ .., count, last] = &instructions[..] else {
+        bail!("Not a multisig script: {script}");
+    };
+    if last.opcode() != Some(OP_CHECKMULTISIG) {
+        bail!("Not a multisig script: {script}");
+    }
+    let keys = keys
+        .iter()
+        .map(|key| {
+            let bytes = key.push_bytes().context("Multisig keys must be pushed")?;
+            Ok(PublicKey::from_slice(bytes.as_bytes())?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let required = first
+        .script_num()
+        .context("Multisig threshold is not a number")?;
+    let total = count
+        .script_num()
+        .context("Multisig key count is not a number")?;
+    if usize::try_from(total)? != keys.len() {
+        bail!(
+            "Multisig script claims {total} keys but holds {}",
+            keys.len()
+        );
+    }
+    Ok((usize::try_from(required)?, keys))
+}
+
+/// `private_keys` sorted by the position of their public key in a multisig `script`.
+/// There must be exactly `m` of them: extra signatures would break NULLDUMMY and CLEANSTACK.
+fn in_script_order(
+    script: &Script,
+    private_keys: &[SecretKey],
+    max_keys: usize,
+    secp: &Secp256k1<All>,
+) -> Result<Vec<SecretKey>> {
+    let (required, keys) = parse_multisig(script)?;
+    if keys.len() > max_keys {
+        bail!("{} keys is over the limit of {max_keys}", keys.len());
+    }
+    if private_keys.len() != required {
+        bail!(
+            "A {required} of {} multisig takes {required} keys, got {}",
+            keys.len(),
+            private_keys.len()
+        );
+    }
+
+    let mut keyed = Vec::new();
+    for private_key in private_keys {
+        let public_key = private_key.public_key(secp);
+        let position = keys
+            .iter()
+            .position(|key| *key == public_key)
+            .with_context(|| format!("{public_key} is not a key of the script"))?;
+        if keyed.iter().any(|(taken, _)| *taken == position) {
+            bail!("{public_key} is given twice");
+        }
+        keyed.push((position, *private_key));
+    }
+    keyed.sort_by_key(|(position, _)| *position);
+    Ok(keyed
+        .into_iter()
+        .map(|(_, private_key)| private_key)
+        .collect())
+}
+
+/// Commits `scripts` to `internal_key` in a tapscript tree, every leaf at the same weight
+/// so the tree comes out balanced. No scripts gives a key-path only output.
+pub fn tapscript_tree(
+    secp: &Secp256k1<All>,
+    internal_key: XOnlyPublicKey,
+    scripts: &[ScriptBuf],
+) -> Result<TaprootSpendInfo> {
+    let builder = if scripts.is_empty() {
+        TaprootBuilder::new()
+    } else {
+        TaprootBuilder::with_huffman_tree(scripts.iter().map(|script| (1, script.clone())))?
+    };
+    builder
+        .finalize(secp, internal_key)
+        .map_err(|_| anyhow!("Tapscript tree is incomplete"))
+}
+
+/// Control block proving `script` is a leaf of the tree behind `spend_info`
+#[allow(dead_code)] // no level spends through a tapscript leaf yet
+pub fn control_block(spend_info: &TaprootSpendInfo, script: &Script) -> Result<ControlBlock> {
+    spend_info
+        .control_block(&(script.to_owned(), LeafVersion::TapScript))
+        .context("Script is not a leaf of the tapscript tree")
+}
+
+/// BIP341 sighash of an input spent through the `script` leaf
+pub fn script_path_sighash(
+    transaction: &Transaction,
+    input_idx: usize,
+    prevouts: &[&TxOut],
+    script: &Script,
+    sighash_type: TapSighashType,
+) -> Result<TapSighash> {
+    let mut sighash_cache = SighashCache::new(transaction);
+    Ok(sighash_cache.taproot_script_spend_signature_hash(
+        input_idx,
+        &Prevouts::All(prevouts),
+        TapLeafHash::from_script(script, LeafVersion::TapScript),
+        sighash_type,
+    )?)
+}
+
+/// Schnorr signature of an input spent through the `script` leaf, for a key of the script.
+/// Leaf keys are not tweaked, only the output key is.
+#[allow(dead_code, clippy::too_many_arguments)] // no level spends through a tapscript leaf yet
+pub fn sign_script_path(
+    transaction: &Transaction,
+    input_idx: usize,
+    prevouts: &[&TxOut],
+    script: &Script,
+    private_key: SecretKey,
+    sighash_type: TapSighashType,
+    signer: &mut impl Signer,
+    secp: &Secp256k1<All>,
+) -> Result<taproot::Signature> {
+    let sighash = script_path_sighash(transaction, input_idx, prevouts, script, sighash_type)?;
+    let message = Message::from_digest(sighash.to_byte_array());
+    let keypair = private_key.keypair(secp);
+
+    let signature = signer.sign_schnorr(secp, &message, &keypair)?;
+    secp.verify_schnorr(&signature, &message, &keypair.x_only_public_key().0)?;
+    Ok(taproot::Signature {
+        signature,
+        sighash_type,
+    })
+}
+
+/// Spends an input through the `script` leaf with `items` satisfying it:
+/// witness = `<items> <script> <control_block>`, e.g. signatures of [`sign_script_path`]
+/// and puzzle answers, the first item being consumed last
+#[allow(dead_code)] // no level spends through a tapscript leaf yet
+pub fn set_script_path_witness(
+    transaction: &mut Transaction,
+    input_idx: usize,
+    items: &[Vec<u8>],
+    script: &Script,
+    control_block: &ControlBlock,
+) {
+    let mut witness = Witness::from_slice(items);
+    witness.push(script.as_bytes());
+    witness.push(control_block.serialize());
+    transaction.input[input_idx].witness = witness;
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        opcodes::all::{OP_CHECKSIG, OP_PUSHNUM_2, OP_PUSHNUM_3},
+        script::Instruction,
+        secp256k1::schnorr,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::bitcoin::HonestSigner;
+
+    const VALUE: Amount = Amount::from_sat(50_000);
+
+    fn keys(count: usize) -> (Secp256k1<All>, Vec<SecretKey>, Vec<PublicKey>) {
+        let secp = Secp256k1::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(count as u64);
+        let private_keys: Vec<SecretKey> = (0..count).map(|_| SecretKey::new(&mut rng)).collect();
+        let public_keys = private_keys
+            .iter()
+            .map(|key| key.public_key(&secp))
+            .collect();
+        (secp, private_keys, public_keys)
+    }
+
+    fn transaction() -> Transaction {
+        let mut tx_builder = TransactionBuilder::new(Amount::from_sat(40_000));
+        tx_builder.add_input(Txid::all_zeros(), 0);
+        tx_builder.add_input(Txid::all_zeros(), 1);
+        tx_builder.build()
+    }
+
+    fn signer() -> HonestSigner<ChaCha20Rng> {
+        HonestSigner {
+            rng: ChaCha20Rng::seed_from_u64(0),
+        }
+    }
+
+    fn pushes(script: &Script) -> Vec<Vec<u8>> {
+        script
+            .instructions()
+            .map(|instruction| match instruction.unwrap() {
+                Instruction::PushBytes(bytes) => bytes.as_bytes().to_vec(),
+                Instruction::Op(op) => panic!("unexpected {op}"),
+            })
+            .collect()
+    }
+
+    /// Checks a DER signature with its sighash byte against `message`
+    fn assert_signed(
+        secp: &Secp256k1<All>,
+        item: &[u8],
+        message: &Message,
+        public_key: &PublicKey,
+    ) {
+        let signature = ecdsa::Signature::from_slice(item).unwrap();
+        assert_eq!(signature.sighash_type, EcdsaSighashType::All);
+        secp.verify_ecdsa(message, &signature.signature, public_key)
+            .unwrap();
+    }
+
+    #[test]
+    fn multisig_script_layout() {
+        let (_, _, public_keys) = keys(3);
+        let script = multisig_script(2, &public_keys).unwrap();
+        let bytes = script.as_bytes();
+        assert_eq!(bytes[0], OP_PUSHNUM_2.to_u8());
+        assert_eq!(bytes[bytes.len() - 2], OP_PUSHNUM_3.to_u8());
+        assert_eq!(bytes[bytes.len() - 1], OP_CHECKMULTISIG.to_u8());
+        assert_eq!(bytes.len(), 3 + 3 * 34);
+        assert_eq!(parse_multisig(&script).unwrap(), (2, public_keys));
+    }
+
+    #[test]
+    fn multisig_script_bounds() {
+        let (_, _, public_keys) = keys(21);
+        assert!(multisig_script(0, &public_keys[..3]).is_err());
+        assert!(multisig_script(4, &public_keys[..3]).is_err());
+        assert!(multisig_script(1, &public_keys).is_err());
+        // past 16 the counts are pushed as numbers instead of OP_n
+        let script = multisig_script(17, &public_keys[..20]).unwrap();
+        assert_eq!(parse_multisig(&script).unwrap().0, 17);
+    }
+
+    #[test]
+    fn keys_follow_the_script_order() {
+        let (secp, private_keys, public_keys) = keys(3);
+        let script = multisig_script(2, &public_keys).unwrap();
+        let ordered = in_script_order(
+            &script,
+            &[private_keys[2], private_keys[0]],
+            MAX_MULTISIG_KEYS,
+            &secp,
+        )
+        .unwrap();
+        assert_eq!(ordered, [private_keys[0], private_keys[2]]);
+    }
+
+    #[test]
+    fn keys_must_match_the_threshold() {
+        let (secp, private_keys, public_keys) = keys(3);
+        let script = multisig_script(2, &public_keys).unwrap();
+        let order = |keys: &[SecretKey]| in_script_order(&script, keys, MAX_MULTISIG_KEYS, &secp);
+        assert!(order(&private_keys[..1]).is_err());
+        assert!(order(&private_keys).is_err());
+        assert!(order(&[private_keys[1], private_keys[1]]).is_err());
+        let (_, strangers, _) = keys(2);
+        assert!(order(&[private_keys[0], strangers[0]]).is_err());
+    }
+
+    #[test]
+    fn p2sh_multisig_is_limited_to_15_keys() {
+        let (secp, private_keys, public_keys) = keys(16);
+        let script = multisig_script(1, &public_keys).unwrap();
+        let mut tx = transaction();
+        assert!(add_p2sh_multisig_signatures(
+            &mut tx,
+            0,
+            &script,
+            &private_keys[..1],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .is_err());
+        assert!(add_p2wsh_multisig_signatures(
+            &mut tx,
+            0,
+            &script,
+            VALUE,
+            &private_keys[..1],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn p2pkh_script_sig_shape() {
+        let (secp, private_keys, public_keys) = keys(1);
+        let prevout = TxOut {
+            value: VALUE,
+            script_pubkey: ScriptBuf::new_p2pkh(
+                &bitcoin::PublicKey::new(public_keys[0]).pubkey_hash(),
+            ),
+        };
+        let mut tx = transaction();
+        add_p2pkh_signature(
+            &mut tx,
+            1,
+            &prevout,
+            private_keys[0],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .unwrap();
+
+        let items = pushes(&tx.input[1].script_sig);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1], public_keys[0].serialize());
+        assert!(tx.input[1].witness.is_empty());
+        let sighash = SighashCache::new(&tx)
+            .legacy_signature_hash(1, &prevout.script_pubkey, 1)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        assert_signed(&secp, &items[0], &message, &public_keys[0]);
+    }
+
+    #[test]
+    fn p2sh_multisig_script_sig_shape() {
+        let (secp, private_keys, public_keys) = keys(3);
+        let script = multisig_script(2, &public_keys).unwrap();
+        let mut tx = transaction();
+        add_p2sh_multisig_signatures(
+            &mut tx,
+            0,
+            &script,
+            &[private_keys[2], private_keys[1]],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .unwrap();
+
+        // OP_0 shows up as an empty push
+        let items = pushes(&tx.input[0].script_sig);
+        assert_eq!(items.len(), 4);
+        assert!(items[0].is_empty());
+        assert_eq!(items[3], script.as_bytes());
+        let sighash = SighashCache::new(&tx)
+            .legacy_signature_hash(0, &script, 1)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        assert_signed(&secp, &items[1], &message, &public_keys[1]);
+        assert_signed(&secp, &items[2], &message, &public_keys[2]);
+    }
+
+    #[test]
+    fn p2wpkh_witness_shape() {
+        let (secp, private_keys, public_keys) = keys(1);
+        let compressed = bitcoin::CompressedPublicKey(public_keys[0]);
+        let prevout = TxOut {
+            value: VALUE,
+            script_pubkey: ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash()),
+        };
+        let mut tx = transaction();
+        add_p2wpkh_signature(
+            &mut tx,
+            0,
+            &prevout,
+            private_keys[0],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .unwrap();
+
+        let witness = &tx.input[0].witness;
+        assert!(tx.input[0].script_sig.is_empty());
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness.nth(1).unwrap(), public_keys[0].serialize());
+        let sighash = SighashCache::new(&tx)
+            .p2wpkh_signature_hash(0, &prevout.script_pubkey, VALUE, EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        assert_signed(&secp, witness.nth(0).unwrap(), &message, &public_keys[0]);
+    }
+
+    #[test]
+    fn p2wsh_multisig_witness_shape() {
+        let (secp, private_keys, public_keys) = keys(3);
+        let script = multisig_script(2, &public_keys).unwrap();
+        let mut tx = transaction();
+        add_p2wsh_multisig_signatures(
+            &mut tx,
+            1,
+            &script,
+            VALUE,
+            &[private_keys[1], private_keys[0]],
+            EcdsaSighashType::All,
+            &mut signer(),
+            &secp,
+        )
+        .unwrap();
+
+        let witness = &tx.input[1].witness;
+        assert!(tx.input[1].script_sig.is_empty());
+        assert_eq!(witness.len(), 4);
+        assert!(witness.nth(0).unwrap().is_empty());
+        assert_eq!(witness.nth(3).unwrap(), script.as_bytes());
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(1, &script, VALUE, EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        assert_signed(&secp, witness.nth(1).unwrap(), &message, &public_keys[0]);
+        assert_signed(&secp, witness.nth(2).unwrap(), &message, &public_keys[1]);
+    }
+
+    /// `<leaf key> OP_CHECKSIG` leaves, one per key
+    fn leaves(secp: &Secp256k1<All>, private_keys: &[SecretKey]) -> Vec<ScriptBuf> {
+        private_keys
+            .iter()
+            .map(|key| {
+                Builder::new()
+                    .push_x_only_key(&key.x_only_public_key(secp).0)
+                    .push_opcode(OP_CHECKSIG)
+                    .into_script()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn control_blocks_commit_to_the_output_key() {
+        let (secp, private_keys, _) = keys(4);
+        let internal_key = private_keys[0].x_only_public_key(&secp).0;
+        for count in 2..=3 {
+            let scripts = leaves(&secp, &private_keys[1..=count]);
+            let spend_info = tapscript_tree(&secp, internal_key, &scripts).unwrap();
+            let output_key = spend_info.output_key().to_inner();
+            for script in &scripts {
+                let control_block = control_block(&spend_info, script).unwrap();
+                assert_eq!(control_block.internal_key, internal_key);
+                assert!(control_block.verify_taproot_commitment(&secp, output_key, script));
+            }
+            let stranger = leaves(&secp, &private_keys[..1]);
+            assert!(control_block(&spend_info, &stranger[0]).is_err());
+        }
+    }
+
+    #[test]
+    fn script_path_witness_shape() {
+        let (secp, private_keys, _) = keys(3);
+        let internal_key = private_keys[0].x_only_public_key(&secp).0;
+        let scripts = leaves(&secp, &private_keys[1..]);
+        let spend_info = tapscript_tree(&secp, internal_key, &scripts).unwrap();
+        let prevouts = [
+            TxOut {
+                value: VALUE,
+                script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+            },
+            TxOut {
+                value: VALUE,
+                script_pubkey: ScriptBuf::new_p2tr_tweaked(spend_info.output_key()),
+            },
+        ];
+        let prevouts: Vec<&TxOut> = prevouts.iter().collect();
+        let script = &scripts[1];
+        let mut tx = transaction();
+        let signature = sign_script_path(
+            &tx,
+            1,
+            &prevouts,
+            script,
+            private_keys[2],
+            TapSighashType::Default,
+            &mut signer(),
+            &secp,
+        )
+        .unwrap();
+        let control_block = control_block(&spend_info, script).unwrap();
+        set_script_path_witness(&mut tx, 1, &[signature.to_vec()], script, &control_block);
+
+        let witness = &tx.input[1].witness;
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.nth(1).unwrap(), script.as_bytes());
+        assert_eq!(witness.nth(2).unwrap(), control_block.serialize());
+        assert_eq!(witness.tapscript(), Some(script.as_script()));
+        // the default sighash type is left out of the signature
+        let signature = schnorr::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+        let sighash =
+            script_path_sighash(&tx, 1, &prevouts, script, TapSighashType::Default).unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        let leaf_key = private_keys[2].x_only_public_key(&secp).0;
+        secp.verify_schnorr(&signature, &message, &leaf_key)
+            .unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+// Player Wallet
+// Every game session gets its own named wallet inside the regtest node
+
+use anyhow::{Context, Result};
+use bitcoin::{Address, Network, Script};
+use bitcoind::{
+    bitcoincore_rpc::{Client, RpcApi},
+    BitcoinD,
+};
+use colored::Colorize;
+
+const PLAYER_WALLET: &str = "player";
+
+pub struct PlayerWallet {
+    pub name: String,
+    /// rpc client bound to the player wallet
+    pub client: Client,
+    /// receiving address shown to the player
+    pub address: Address,
+    /// output descriptor of the receiving address
+    pub descriptor: String,
+}
+
+impl PlayerWallet {
+    /// Creates the player wallet in the node and derives its receiving address
+    pub fn create(bitcoind: &BitcoinD) -> Result<Self> {
+        let client = bitcoind
+            .create_wallet(PLAYER_WALLET)
+            .context("Failed to create player wallet")?;
+
+        let address = client
+            .get_new_address(Some("player"), None)?
+            .require_network(Network::Regtest)?;
+
+        let address_info: serde_json::Value =
+            client.call("getaddressinfo", &[address.to_string().into()])?;
+        let descriptor = address_info["desc"]
+            .as_str()
+            .context("Player address has no descriptor")?
+            .to_string();
+
+        Ok(Self {
+            name: PLAYER_WALLET.to_string(),
+            client,
+            address,
+            descriptor,
+        })
+    }
+
+    /// Whether `script` pays an address owned by the player wallet
+    #[allow(dead_code)]
+    pub fn owns(&self, script: &Script) -> Result<bool> {
+        Self::owned_by(&self.client, script)
+    }
+
+    /// Whether `script` pays an address owned by the wallet `client` is bound to,
+    /// for checks running on a client of their own
+    pub fn owned_by(client: &Client, script: &Script) -> Result<bool> {
+        let Ok(address) = Address::from_script(script, Network::Regtest) else {
+            return Ok(false);
+        };
+        let address_info = client.get_address_info(&address)?;
+        Ok(address_info.is_mine.unwrap_or(false))
+    }
+
+    pub fn print_details(&self) {
+        println!("\n{}", "Your Wallet:".cyan().bold());
+        println!("{} {}", "Name:".cyan(), self.name);
+        println!(
+            "{} {}",
+            "Address:".cyan(),
+            self.address.to_string().bright_magenta()
+        );
+        println!("{} {}", "Descriptor:".cyan(), self.descriptor);
+    }
+}
@@ -0,0 +1,502 @@
+// Weak Nonces
+// Nonce sources of broken wallets, each one a `Signer` for both Schnorr and ECDSA.
+// Whoever finds the nonce k of a signature recovers the key from it:
+// x = (s - k) / e for Schnorr, x = (s * k - z) / r for ECDSA (mod n).
+// Every flaw is parameterized, level authors pick the weakness and its difficulty.
+// Signatures only pin k down to its sign: BIP340 negates k when R has an odd y and
+// ECDSA signatures are normalized to a low s, which amounts to signing with -k.
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    secp256k1::{Message, SecretKey},
+};
+use rand::Rng;
+
+use super::signer::{scalar_from_u64, NonceSource};
+
+/// k drawn uniformly from 1..=`max`, found by trying at most `max` values.
+/// Difficulty grows with `max`: 2^8 is instant, 2^40 takes a baby-step giant-step.
+pub struct SmallNonce<R> {
+    pub max: u64,
+    pub rng: R,
+}
+
+impl<R: Rng> NonceSource for SmallNonce<R> {
+    fn next_nonce(&mut self, _msg: &Message) -> Result<SecretKey> {
+        let k = scalar_from_u64(self.rng.gen_range(1..=self.max.max(1)));
+        Ok(SecretKey::from_slice(&k.to_be_bytes())?)
+    }
+}
+
+/// The same k for every signature.
+/// Two signatures of the same key solve for k, and then for the key.
+/// Each signature may have used -k instead (see the header), solvers try both signs.
+pub struct ReusedNonce {
+    pub k: SecretKey,
+}
+
+#[allow(dead_code)]
+impl ReusedNonce {
+    /// A reused nonce which is otherwise perfectly random
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self {
+            k: random_nonce(rng, |_| {}),
+        }
+    }
+}
+
+impl NonceSource for ReusedNonce {
+    fn next_nonce(&mut self, _msg: &Message) -> Result<SecretKey> {
+        Ok(self.k)
+    }
+}
+
+/// Which end of a biased nonce is known
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum KnownBits {
+    /// the most significant bits are zero
+    Prefix,
+    /// the least significant bits are zero
+    Suffix,
+}
+
+/// Random k with `known_bits` bits at one end set to zero.
+/// A hidden number problem: a lattice recovers the key from about 256 / `known_bits` signatures,
+/// fewer known bits need more signatures and a bigger lattice.
+#[allow(dead_code)]
+pub struct BiasedNonce<R> {
+    pub known_bits: u32,
+    pub side: KnownBits,
+    pub rng: R,
+}
+
+impl<R: Rng> NonceSource for BiasedNonce<R> {
+    fn next_nonce(&mut self, _msg: &Message) -> Result<SecretKey> {
+        ensure!(
+            self.known_bits < 256,
+            "A nonce can't have {} known bits",
+            self.known_bits
+        );
+        let (known_bits, side) = (self.known_bits, self.side);
+        Ok(random_nonce(&mut self.rng, |bytes| {
+            for bit in 0..known_bits {
+                // bit 0 is the most significant one
+                let bit = match side {
+                    KnownBits::Prefix => bit,
+                    KnownBits::Suffix => 255 - bit,
+                };
+                bytes[(bit / 8) as usize] &= !(0x80 >> (bit % 8));
+            }
+        }))
+    }
+}
+
+/// k starting from a secret value and growing by `step` with every signature.
+/// Two signatures of the same key with a known step solve for the key.
+/// Each signature may have used -k instead (see the header), solvers try all four sign pairs.
+pub struct CounterNonce {
+    pub next: SecretKey,
+    pub step: u64,
+}
+
+#[allow(dead_code)]
+impl CounterNonce {
+    /// A counter starting from a random nonce
+    pub fn random(step: u64, rng: &mut impl Rng) -> Self {
+        Self {
+            next: random_nonce(rng, |_| {}),
+            step,
+        }
+    }
+}
+
+impl NonceSource for CounterNonce {
+    fn next_nonce(&mut self, _msg: &Message) -> Result<SecretKey> {
+        let k = self.next;
+        self.next = k.add_tweak(&scalar_from_u64(self.step))?;
+        Ok(k)
+    }
+}
+
+/// k = sha256(`tag` || m), derived from the message alone.
+/// Anyone knowing the tag computes k, and keys signing the same message share it.
+#[allow(dead_code)]
+pub struct MessageNonce {
+    pub tag: &'static str,
+}
+
+impl NonceSource for MessageNonce {
+    fn next_nonce(&mut self, msg: &Message) -> Result<SecretKey> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(self.tag.as_bytes());
+        engine.input(msg.as_ref());
+        // a hash above the curve order is astronomically unlikely
+        Ok(SecretKey::from_slice(
+            sha256::Hash::from_engine(engine).as_byte_array(),
+        )?)
+    }
+}
+
+/// k(i+1) = `multiplier` * k(i) + `increment`, one generator shared by every key of a wallet.
+/// Consecutive nonces are related even across keys: with known parameters every pair of
+/// signatures gives a linear equation in the keys, as many pairs as keys solve for all of them.
+/// Each signature may have used -k instead (see the header), solvers try every sign pair.
+/// `multiplier` must not be zero, nonces would otherwise collapse to `increment`.
+pub struct LinearNonce {
+    pub state: SecretKey,
+    pub multiplier: u64,
+    pub increment: u64,
+}
+
+#[allow(dead_code)]
+impl LinearNonce {
+    /// A generator starting from a random state
+    pub fn random(multiplier: u64, increment: u64, rng: &mut impl Rng) -> Result<Self> {
+        ensure!(
+            multiplier != 0,
+            "A linear nonce needs a non zero multiplier"
+        );
+        Ok(Self {
+            state: random_nonce(rng, |_| {}),
+            multiplier,
+            increment,
+        })
+    }
+}
+
+impl NonceSource for LinearNonce {
+    fn next_nonce(&mut self, _msg: &Message) -> Result<SecretKey> {
+        ensure!(
+            self.multiplier != 0,
+            "A linear nonce needs a non zero multiplier"
+        );
+        let k = self.state;
+        self.state = k
+            .mul_tweak(&scalar_from_u64(self.multiplier))?
+            .add_tweak(&scalar_from_u64(self.increment))
+            .context("Linear nonce generator reached zero")?;
+        Ok(k)
+    }
+}
+
+/// Draws random bytes shaped by `shape` until they are a valid nonce
+fn random_nonce(rng: &mut impl Rng, shape: impl Fn(&mut [u8; 32])) -> SecretKey {
+    loop {
+        let mut bytes: [u8; 32] = rng.gen();
+        shape(&mut bytes);
+        if let Ok(k) = SecretKey::from_slice(&bytes) {
+            return k;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        key::Secp256k1,
+        secp256k1::{ecdsa, All, PublicKey, Scalar},
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::bitcoin::{invert, Signer};
+
+    /// What an attacker reads from an ECDSA signature: s * k = z + r * x
+    struct Observed {
+        z: SecretKey,
+        r: SecretKey,
+        s: SecretKey,
+    }
+
+    fn add(a: SecretKey, b: SecretKey) -> SecretKey {
+        a.add_tweak(&Scalar::from(b)).unwrap()
+    }
+
+    fn sub(a: SecretKey, b: SecretKey) -> SecretKey {
+        add(a, b.negate())
+    }
+
+    fn mul(a: SecretKey, b: SecretKey) -> SecretKey {
+        a.mul_tweak(&Scalar::from(b)).unwrap()
+    }
+
+    fn div(a: SecretKey, b: SecretKey) -> SecretKey {
+        mul(a, invert(b).unwrap())
+    }
+
+    fn scalar(value: u64) -> SecretKey {
+        SecretKey::from_slice(&scalar_from_u64(value).to_be_bytes()).unwrap()
+    }
+
+    fn setup(seed: u64) -> (Secp256k1<All>, ChaCha20Rng, SecretKey) {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let key = SecretKey::new(&mut rng);
+        (Secp256k1::new(), rng, key)
+    }
+
+    /// Signs message `idx` and returns what the attacker sees
+    fn sign(signer: &mut impl Signer, secp: &Secp256k1<All>, key: &SecretKey, idx: u8) -> Observed {
+        let msg = Message::from_digest([idx + 1; 32]);
+        let signature = signer.sign_ecdsa(secp, &msg, key).unwrap();
+        secp.verify_ecdsa(&msg, &signature, &key.public_key(secp))
+            .unwrap();
+        observe(&msg, &signature)
+    }
+
+    fn observe(msg: &Message, signature: &ecdsa::Signature) -> Observed {
+        let compact = signature.serialize_compact();
+        Observed {
+            z: SecretKey::from_slice(msg.as_ref()).unwrap(),
+            r: SecretKey::from_slice(&compact[..32]).unwrap(),
+            s: SecretKey::from_slice(&compact[32..]).unwrap(),
+        }
+    }
+
+    /// x = (s * k - z) / r
+    fn key_from_nonce(sig: &Observed, k: SecretKey) -> Option<SecretKey> {
+        let scaled = mul(sig.s, k);
+        (scaled != sig.z).then(|| div(sub(scaled, sig.z), sig.r))
+    }
+
+    /// Solves a pair of signatures of the same key whose nonces satisfy
+    /// k2 = a * k1 + b, trying every sign the signer may have applied
+    fn solve_linear(
+        first: &Observed,
+        second: &Observed,
+        a: SecretKey,
+        b: SecretKey,
+    ) -> Vec<SecretKey> {
+        let mut candidates = Vec::new();
+        for s1 in [first.s, first.s.negate()] {
+            for s2 in [second.s, second.s.negate()] {
+                // k1 = (z1 + r1 * x) / s1 and s2 * (a * k1 + b) = z2 + r2 * x
+                let ratio = div(mul(s2, a), s1);
+                let lhs = sub(mul(ratio, first.r), second.r);
+                let rhs = sub(sub(second.z, mul(s2, b)), mul(ratio, first.z));
+                candidates.push(div(rhs, lhs));
+            }
+        }
+        candidates
+    }
+
+    fn assert_recovered(secp: &Secp256k1<All>, key: &SecretKey, candidates: &[SecretKey]) {
+        let public_key = key.public_key(secp);
+        assert!(
+            candidates
+                .iter()
+                .any(|candidate| candidate.public_key(secp) == public_key),
+            "key not recovered"
+        );
+    }
+
+    /// Walks k = start, start + step, ... and returns the first k whose point matches `r`
+    fn brute_force(
+        secp: &Secp256k1<All>,
+        sig: &Observed,
+        start: SecretKey,
+        step: SecretKey,
+        tries: u64,
+    ) -> SecretKey {
+        let mut k = start;
+        let mut point = start.public_key(secp);
+        let step_point = step.public_key(secp);
+        for _ in 0..tries {
+            if point.serialize()[1..] == sig.r.secret_bytes() {
+                return k;
+            }
+            k = add(k, step);
+            point = point.combine(&step_point).unwrap();
+        }
+        panic!("nonce not found");
+    }
+
+    fn both_signs(sig: &Observed, k: SecretKey) -> Vec<SecretKey> {
+        [k, k.negate()]
+            .into_iter()
+            .filter_map(|k| key_from_nonce(sig, k))
+            .collect()
+    }
+
+    #[test]
+    fn small_nonces_are_brute_forced() {
+        let (secp, mut rng, key) = setup(1);
+        let mut signer = SmallNonce {
+            max: 1 << 12,
+            rng: &mut rng,
+        };
+        for idx in 0..4 {
+            let sig = sign(&mut signer, &secp, &key, idx);
+            let k = brute_force(&secp, &sig, scalar(1), scalar(1), 1 << 12);
+            assert_recovered(&secp, &key, &both_signs(&sig, k));
+        }
+    }
+
+    #[test]
+    fn reused_nonces_leak_the_key() {
+        let (secp, mut rng, key) = setup(2);
+        let mut signer = ReusedNonce::random(&mut rng);
+        let first = sign(&mut signer, &secp, &key, 0);
+        let second = sign(&mut signer, &secp, &key, 1);
+        assert_eq!(first.r, second.r);
+
+        // s1 * k = z1 + r * x and s2 * (+-k) = z2 + r * x
+        let candidates: Vec<SecretKey> = [scalar(1), scalar(1).negate()]
+            .into_iter()
+            .filter_map(|sign| {
+                let k = div(sub(first.z, second.z), sub(first.s, mul(sign, second.s)));
+                key_from_nonce(&first, k)
+            })
+            .collect();
+        assert_recovered(&secp, &key, &candidates);
+    }
+
+    #[test]
+    fn counter_nonces_leak_the_key() {
+        let (secp, mut rng, key) = setup(3);
+        let mut signer = CounterNonce::random(7, &mut rng);
+        let first = sign(&mut signer, &secp, &key, 0);
+        let second = sign(&mut signer, &secp, &key, 1);
+        assert_recovered(
+            &secp,
+            &key,
+            &solve_linear(&first, &second, scalar(1), scalar(7)),
+        );
+    }
+
+    #[test]
+    fn message_nonces_are_recomputed() {
+        let (secp, _, key) = setup(4);
+        let mut signer = MessageNonce { tag: "phishy" };
+        let sig = sign(&mut signer, &secp, &key, 0);
+        let k = MessageNonce { tag: "phishy" }
+            .next_nonce(&Message::from_digest([1; 32]))
+            .unwrap();
+        assert_recovered(&secp, &key, &both_signs(&sig, k));
+    }
+
+    #[test]
+    fn message_nonces_are_shared_across_keys() {
+        let (secp, mut rng, key) = setup(5);
+        let other = SecretKey::new(&mut rng);
+        let mut signer = MessageNonce { tag: "phishy" };
+        let first = sign(&mut signer, &secp, &key, 0);
+        let second = sign(&mut signer, &secp, &other, 0);
+        assert_eq!(first.r, second.r);
+    }
+
+    #[test]
+    fn linear_nonces_leak_the_key() {
+        let (secp, mut rng, key) = setup(6);
+        let mut signer = LinearNonce::random(5, 11, &mut rng).unwrap();
+        let first = sign(&mut signer, &secp, &key, 0);
+        let second = sign(&mut signer, &secp, &key, 1);
+        assert_recovered(
+            &secp,
+            &key,
+            &solve_linear(&first, &second, scalar(5), scalar(11)),
+        );
+    }
+
+    #[test]
+    fn linear_nonces_leak_every_key_sharing_them() {
+        let (secp, mut rng, alice) = setup(7);
+        let bob = SecretKey::new(&mut rng);
+        let (a, b) = (scalar(5), scalar(11));
+        let mut signer = LinearNonce::random(5, 11, &mut rng).unwrap();
+        let alice_first = sign(&mut signer, &secp, &alice, 0);
+        let bob_sig = sign(&mut signer, &secp, &bob, 1);
+        let alice_second = sign(&mut signer, &secp, &alice, 2);
+
+        // k3 = a^2 * k1 + a * b + b
+        let candidates = solve_linear(&alice_first, &alice_second, mul(a, a), add(mul(a, b), b));
+        assert_recovered(&secp, &alice, &candidates);
+
+        // Alice's key gives her nonce, and the next one is Bob's
+        let mut bob_candidates = Vec::new();
+        for x in candidates {
+            let k1 = div(add(alice_first.z, mul(alice_first.r, x)), alice_first.s);
+            for k1 in [k1, k1.negate()] {
+                bob_candidates.extend(both_signs(&bob_sig, add(mul(a, k1), b)));
+            }
+        }
+        assert_recovered(&secp, &bob, &bob_candidates);
+    }
+
+    #[test]
+    fn linear_nonces_reject_a_zero_multiplier() {
+        let (_, mut rng, _) = setup(8);
+        assert!(LinearNonce::random(0, 1, &mut rng).is_err());
+        let mut signer = LinearNonce {
+            state: SecretKey::new(&mut rng),
+            multiplier: 0,
+            increment: 1,
+        };
+        assert!(signer.next_nonce(&Message::from_digest([1; 32])).is_err());
+    }
+
+    /// With nearly every bit known the rest is brute forced, standing in for a lattice
+    #[test]
+    fn biased_nonces_are_brute_forced() {
+        let (secp, mut rng, key) = setup(9);
+        let mut prefix = BiasedNonce {
+            known_bits: 244,
+            side: KnownBits::Prefix,
+            rng: &mut rng,
+        };
+        let sig = sign(&mut prefix, &secp, &key, 0);
+        let k = brute_force(&secp, &sig, scalar(1), scalar(1), 1 << 12);
+        assert_recovered(&secp, &key, &both_signs(&sig, k));
+
+        let mut suffix = BiasedNonce {
+            known_bits: 244,
+            side: KnownBits::Suffix,
+            rng: &mut rng,
+        };
+        let sig = sign(&mut suffix, &secp, &key, 1);
+        let mut step = [0u8; 32];
+        step[1] = 0x10; // 2^244
+        let step = SecretKey::from_slice(&step).unwrap();
+        let k = brute_force(&secp, &sig, step, step, 1 << 12);
+        assert_recovered(&secp, &key, &both_signs(&sig, k));
+    }
+
+    #[test]
+    fn biased_nonces_have_their_known_bits_cleared() {
+        let (_, mut rng, _) = setup(10);
+        let msg = Message::from_digest([1; 32]);
+        for side in [KnownBits::Prefix, KnownBits::Suffix] {
+            let mut signer = BiasedNonce {
+                known_bits: 12,
+                side,
+                rng: &mut rng,
+            };
+            for _ in 0..8 {
+                let k = signer.next_nonce(&msg).unwrap().secret_bytes();
+                match side {
+                    KnownBits::Prefix => assert!(k[0] == 0 && k[1] < 0x10),
+                    KnownBits::Suffix => assert!(k[31] == 0 && k[30].trailing_zeros() >= 4),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn schnorr_signatures_leak_the_nonce_too() {
+        let (secp, _, key) = setup(11);
+        let keypair = key.keypair(&secp);
+        let msg = Message::from_digest([3; 32]);
+        let signature = MessageNonce { tag: "phishy" }
+            .sign_schnorr(&secp, &msg, &keypair)
+            .unwrap();
+        secp.verify_schnorr(&signature, &msg, &keypair.x_only_public_key().0)
+            .unwrap();
+        let r = PublicKey::from_secret_key(
+            &secp,
+            &MessageNonce { tag: "phishy" }.next_nonce(&msg).unwrap(),
+        );
+        assert_eq!(r.serialize()[1..], signature.serialize()[..32]);
+    }
+}
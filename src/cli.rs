@@ -1,5 +1,7 @@
 // CLI commands
 
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -13,6 +15,14 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// Path to the bitcoind executable
+    #[arg(long, global = true, value_name = "PATH")]
+    pub bitcoind: Option<PathBuf>,
+
+    /// Seed the level is generated from, the same seed replays the same keys and transactions
+    #[arg(long, global = true, value_name = "SEED")]
+    pub seed: Option<u64>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -27,6 +37,41 @@ pub enum Commands {
         #[arg(value_name = "LEVEL")]
         level: u32,
     },
+    /// Send an RPC call to the node of the running level
+    Rpc {
+        /// Session to send the call to, defaults to the most recent running one
+        #[arg(long, value_name = "ID")]
+        session: Option<String>,
+        /// The RPC method, e.g. getrawmempool
+        #[arg(value_name = "METHOD")]
+        method: String,
+        /// Method parameters, parsed as JSON when possible
+        #[arg(value_name = "PARAMS", allow_hyphen_values = true)]
+        params: Vec<String>,
+    },
+    /// List running level nodes and kill orphaned ones
+    Sessions {
+        /// Stop the node of the given session and remove its data
+        #[arg(long, value_name = "ID")]
+        kill: Option<String>,
+        /// Stop every orphaned node and remove its data
+        #[arg(long, conflicts_with = "kill")]
+        prune: bool,
+    },
+    /// Check that bitcoind is installed and supported
+    Doctor,
     /// Display game statistics
-    Stats,
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Play levels with their reference solvers, fails unless every objective is met
+    #[cfg(feature = "solvers")]
+    #[command(hide = true)]
+    Solve {
+        /// Only solve this level, defaults to every registered level
+        #[arg(value_name = "LEVEL")]
+        level: Option<u32>,
+    },
 }
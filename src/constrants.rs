@@ -1,3 +1,11 @@
 use bitcoin::Amount;
 
 pub const PROJECTED_FEE: Amount = Amount::from_sat(1000);
+
+pub const PASSING_PERCENTAGE: u32 = 60;
+
+/// every regtest node gets its own data directory in here
+pub const SESSIONS_DIR: &str = "bin/bitcoin/sessions";
+
+/// cached chain snapshots new sessions are copied from
+pub const SNAPSHOTS_DIR: &str = "bin/bitcoin/snapshots";
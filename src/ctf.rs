@@ -1,25 +1,42 @@
 // Ctf Runner
 
-use anyhow::{Ok, Result};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Ok, Result};
 use clap::CommandFactory;
 use colored::Colorize;
+use serde_json::Value;
 
 use crate::{
+    bitcoin::{BitcoindBinary, FrameworkConfig, Session, SUPPORTED_VERSIONS},
     cli::Cli,
-    level::{start_level, Level},
-    levels::LevelOne,
+    constrants::PASSING_PERCENTAGE,
+    level::{Level, LevelFactory, LevelInfo, LevelOutcome, Registry},
+    levels,
+    scoring::{passes, Attempt, Score},
     state::State,
+    stats::Stats,
 };
 
 pub struct Ctf {
     state: State,
+    registry: Registry,
+    config: FrameworkConfig,
 }
 
 impl Ctf {
-    pub fn new() -> Result<Self> {
+    pub fn new(cli: &Cli) -> Result<Self> {
         // Initialize game state and levels
         Ok(Self {
             state: State::load()?,
+            registry: levels::registry(),
+            config: FrameworkConfig {
+                bitcoind: cli.bitcoind.clone(),
+                seed: cli.seed,
+            },
         })
     }
 
@@ -27,8 +44,19 @@ impl Ctf {
         match &cli.command {
             Some(crate::cli::Commands::New) => self.start_new_level().await,
             Some(crate::cli::Commands::Continue) => self.continue_game().await,
-            Some(crate::cli::Commands::Retry { level }) => self.retry_level(level).await,
-            Some(crate::cli::Commands::Stats) => Ok(()),
+            Some(crate::cli::Commands::Retry { level }) => self.retry_level(*level).await,
+            Some(crate::cli::Commands::Rpc {
+                session,
+                method,
+                params,
+            }) => rpc(session.as_deref(), method, params),
+            Some(crate::cli::Commands::Sessions { kill, prune }) => {
+                sessions(kill.as_deref(), *prune)
+            }
+            Some(crate::cli::Commands::Doctor) => self.doctor(),
+            Some(crate::cli::Commands::Stats { json }) => self.show_stats(*json),
+            #[cfg(feature = "solvers")]
+            Some(crate::cli::Commands::Solve { level }) => self.solve_levels(*level).await,
             None => {
                 // Display ASCII art logo
                 println!("{}", get_ascii_logo().green());
@@ -49,31 +77,358 @@ impl Ctf {
         // start from level 1
         self.state.initialize_state()?;
 
-        LevelOne::print_problem_statement();
-        let lvl1 = start_level::<LevelOne>().await?;
-        if lvl1.run().await? {
-            self.state.complete_level(1, 100)?;
-            self.state.save()?;
+        self.play_level(1).await
+    }
+
+    async fn continue_game(&mut self) -> Result<()> {
+        let level = self.state.current_level();
+        println!("{} {}", "Continuing the game from level".green(), level);
+        self.play_level(level).await
+    }
+
+    async fn retry_level(&mut self, level: u32) -> Result<()> {
+        // only levels the player has already unlocked can be replayed
+        if level == 0 || level > self.state.current_level() {
+            println!(
+                "{}",
+                format!(
+                    "Level {level} is locked. You can retry levels 1 to {}.",
+                    self.state.current_level()
+                )
+                .red()
+            );
+            return Ok(());
         }
 
-        Ok(())
+        println!("{}", format!("Retrying level {level}").green());
+        self.play_level(level).await
     }
 
-    async fn continue_game(&self) -> Result<()> {
-        // Note : before continuing the game if current level > 1, then check if user passed previous level with atleast 60% of score.
+    /// Checks bitcoind can be found and is a supported Bitcoin Core version
+    fn doctor(&self) -> Result<()> {
+        println!("{}", "Checking your setup...".green());
+
+        let binary = BitcoindBinary::resolve(self.config.bitcoind.as_deref())
+            .inspect_err(|_| println!("{} bitcoind not found", "✗".red()))?;
+        println!(
+            "{} bitcoind found at {} (from {})",
+            "✓".green(),
+            binary.path.display(),
+            binary.source
+        );
+
+        let (major, minor) = binary
+            .version()
+            .inspect_err(|_| println!("{} could not read the bitcoind version", "✗".red()))?;
+        println!("{} Bitcoin Core v{major}.{minor}", "✓".green());
+
+        binary
+            .preflight()
+            .inspect_err(|_| println!("{} version not supported", "✗".red()))?;
+        println!(
+            "{} version supported (v{} to v{})",
+            "✓".green(),
+            SUPPORTED_VERSIONS.start(),
+            SUPPORTED_VERSIONS.end()
+        );
+
         println!(
-            "{} {}",
-            "Continuing the game from level".green(),
-            self.state.current_level()
+            "{}",
+            "All good, you are ready to play!".bright_green().bold()
         );
         Ok(())
     }
 
-    async fn retry_level(&self, level: &u32) -> Result<()> {
-        // Implement retry level logic
-        println!("{}", format!("Retrying level {level}").green());
+    fn show_stats(&self, json: bool) -> Result<()> {
+        let stats = Stats::new(&self.state, &self.registry);
+        if json {
+            println!("{}", stats.to_json()?);
+        } else {
+            stats.print();
+        }
+        Ok(())
+    }
+
+    /// Plays levels with their reference solvers, leaving the game state untouched.
+    /// Fails unless every solver scores the full points of its level on a first attempt.
+    #[cfg(feature = "solvers")]
+    async fn solve_levels(&self, only: Option<u32>) -> Result<()> {
+        for level in self.registry.iter() {
+            let info = level.info();
+            if only.is_some_and(|number| number != info.number) {
+                continue;
+            }
+            println!("{}", format!("Solving level {}", info.number).green());
+            let (outcome, time_used) = crate::solvers::solve(level, &self.config).await?;
+            let attempt = Attempt {
+                outcome,
+                time_used,
+                time_limit: info.time_limit,
+                hints_used: 0,
+                attempts: 1,
+                replay: false,
+            };
+            let score = level.scoring().score(&attempt, info.max_score);
+            print_score(&attempt, &score);
+            if score.points < info.max_score {
+                bail!(
+                    "Level {} was not fully solved: scored {}/{}",
+                    info.number,
+                    score.points,
+                    info.max_score
+                );
+            }
+            println!("{}", format!("Level {} solved", info.number).bright_green());
+        }
+        Ok(())
+    }
+
+    /// Looks up the level by its number and plays it
+    async fn play_level(&mut self, number: u32) -> Result<()> {
+        let Some(level) = self.registry.shared(number) else {
+            println!(
+                "{}",
+                "You have completed all available levels. Stay tuned for more!"
+                    .bright_yellow()
+                    .bold()
+            );
+            return Ok(());
+        };
+        let info = level.info();
+
+        // player must have passed every prerequisite level, see `scoring::passes`
+        if let Some(prerequisite) = self.unmet_prerequisite(info) {
+            let score = self.state.get_level_score(prerequisite.number).unwrap_or(0);
+            println!(
+                "{}",
+                format!(
+                    "You need at least {PASSING_PERCENTAGE}% on level {} to play level {number} (scored {score}/{}).",
+                    prerequisite.number, prerequisite.max_score
+                )
+                .red()
+            );
+            println!(
+                "{}",
+                format!("Try `retry {}` to improve your score.", prerequisite.number).yellow()
+            );
+            return Ok(());
+        }
+
+        // a fresh seed per attempt unless the player replays one
+        let seed = self.config.seed.unwrap_or_else(rand::random);
+        let config = FrameworkConfig {
+            seed: Some(seed),
+            ..self.config.clone()
+        };
+        // replaying a passed level is practice, it costs no retry or hint penalty
+        let replay = self
+            .state
+            .get_level_score(number)
+            .is_some_and(|score| passes(score, info.max_score));
+
+        let lvl = set_up(Arc::clone(&level), &config).await?;
+        // only attempts that got to be played count as retries
+        self.state.record_attempt(number, seed)?;
+        let played_level = self.state.get_level(number);
+        let attempts = played_level.map_or(1, |l| l.attempts);
+        let hints_used = played_level.map_or(0, |l| l.hints_revealed);
+
+        let (outcome, time_used) = play(lvl, number).await?;
+        let attempt = Attempt {
+            outcome,
+            time_used,
+            time_limit: info.time_limit,
+            hints_used,
+            attempts,
+            replay,
+        };
+        let score = level.scoring().score(&attempt, info.max_score);
+        print_score(&attempt, &score);
+
+        if score.passed {
+            self.state
+                .complete_level(number, score.points, time_used.as_secs())?;
+            return Ok(());
+        }
+        self.state.record_score(number, score.points)?;
+
+        // one more hint gets revealed after every failed attempt
+        let revealed = self
+            .state
+            .reveal_hint(number, u32::try_from(info.hints.len())?)?;
+        for (idx, hint) in info.hints.iter().take(revealed as usize).enumerate() {
+            println!("{} {}", format!("Hint {}:", idx + 1).yellow().bold(), hint);
+        }
+
         Ok(())
     }
+
+    /// Returns the first prerequisite of `info` the player has not passed yet
+    fn unmet_prerequisite(&self, info: &LevelInfo) -> Option<&LevelInfo> {
+        info.prerequisites
+            .iter()
+            .filter_map(|&number| self.registry.get(number))
+            .map(LevelFactory::info)
+            .find(|prerequisite| {
+                let score = self.state.get_level_score(prerequisite.number).unwrap_or(0);
+                !passes(score, prerequisite.max_score)
+            })
+    }
+}
+
+/// Prints the problem statement and sets the level up, Ctrl-C interrupts the setup
+async fn set_up(level: Arc<dyn LevelFactory>, config: &FrameworkConfig) -> Result<Arc<dyn Level>> {
+    print_level_info(level.info());
+    level.print_problem_statement();
+
+    // setup blocks while bitcoind starts and the level gets funded,
+    // on its own thread it does not keep Ctrl-C from being noticed
+    let setup = tokio::task::spawn_blocking({
+        let config = config.clone();
+        let runtime = tokio::runtime::Handle::current();
+        move || runtime.block_on(level.setup(&config))
+    });
+    // an interrupted setup still completes, dropping the level tears its regtest node down
+    tokio::select! {
+        lvl = setup => Ok(Arc::from(lvl.context("Level panicked during setup")??)),
+        _ = tokio::signal::ctrl_c() => bail!("Level interrupted during setup"),
+    }
+}
+
+/// Plays a level once it is set up, `number` being its level number.
+/// `cleanup` runs whether `run` succeeds, fails, panics or is interrupted with Ctrl-C.
+/// Returns the level outcome along with the time spent in `run`.
+async fn play(lvl: Arc<dyn Level>, number: u32) -> Result<(LevelOutcome, Duration)> {
+    lvl.ctf_framework().player.print_details();
+    lvl.ctf_framework().session.rpc.print_details();
+    // serves the level chain until the level is over
+    #[cfg(feature = "esplora")]
+    let _esplora = {
+        let server = lvl.ctf_framework().serve_esplora().await?;
+        server.print_details();
+        server
+    };
+    let seed = lvl.ctf_framework().seed;
+    println!(
+        "{} {seed} {}",
+        "Seed:".cyan().bold(),
+        format!("(replay with `retry {number} --seed {seed}`)").bright_black()
+    );
+
+    let started_at = Instant::now();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut run = tokio::spawn({
+        let lvl = Arc::clone(&lvl);
+        async move { lvl.run().await }
+    });
+    let outcome = tokio::select! {
+        result = &mut run => result.context("Level panicked while running").and_then(|outcome| outcome),
+        _ = &mut ctrl_c => {
+            run.abort();
+            let _ = run.await;
+            Err(anyhow!("Level interrupted"))
+        }
+    };
+    let time_used = started_at.elapsed();
+    lvl.cleanup().await?;
+
+    Ok((outcome?, time_used))
+}
+
+/// Forwards an RPC call to the node of a running level and prints the result
+fn rpc(session: Option<&str>, method: &str, params: &[String]) -> Result<()> {
+    let result = Session::find(session)?.rpc.call(method, params)?;
+    match result {
+        Value::String(result) => println!("{result}"),
+        result => println!("{}", serde_json::to_string_pretty(&result)?),
+    }
+    Ok(())
+}
+
+/// Lists level nodes, or kills the given / orphaned ones
+fn sessions(kill: Option<&str>, prune: bool) -> Result<()> {
+    if let Some(id) = kill {
+        Session::find(Some(id))?.kill()?;
+        println!("{}", format!("Killed session {id}").green());
+        return Ok(());
+    }
+
+    let sessions = Session::list()?;
+    if prune {
+        for session in sessions.iter().filter(|session| session.is_orphaned()) {
+            session.kill()?;
+            println!(
+                "{}",
+                format!("Killed orphaned session {}", session.id).green()
+            );
+        }
+        return Ok(());
+    }
+
+    if sessions.is_empty() {
+        println!("{}", "No level node is running.".bright_white());
+        return Ok(());
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    println!(
+        "{}",
+        format!(
+            "{:<10}{:<10}{:<16}{:<10}{}",
+            "Session", "Pid", "Started", "Status", "RPC URL"
+        )
+        .cyan()
+        .bold()
+    );
+    for session in &sessions {
+        let status = if session.is_orphaned() {
+            "orphaned".red()
+        } else {
+            "running".green()
+        };
+        println!(
+            "{:<10}{:<10}{:<16}{:<10}{}",
+            session.id,
+            session.owner_pid,
+            format!("{}s ago", now.saturating_sub(session.started_at)),
+            status,
+            session.rpc.url
+        );
+    }
+    Ok(())
+}
+
+fn print_score(attempt: &Attempt, score: &Score) {
+    println!(
+        "{} {}/{}  ({}/{} objectives, {} hint(s) used, attempt #{})",
+        "Score:".bright_yellow().bold(),
+        score.points,
+        score.max_points,
+        attempt.outcome.objectives_met,
+        attempt.outcome.objectives_total,
+        attempt.hints_used,
+        attempt.attempts
+    );
+}
+
+fn print_level_info(info: &LevelInfo) {
+    println!(
+        "\n{}",
+        format!("Level {}: {}", info.number, info.title)
+            .bright_cyan()
+            .bold()
+    );
+    println!(
+        "{} {}    {} {}s",
+        "Difficulty:".cyan(),
+        info.difficulty,
+        "Time limit:".cyan(),
+        info.time_limit.as_secs()
+    );
+    println!("{}", "Learning objectives:".cyan());
+    for objective in info.learning_objectives {
+        println!("  • {objective}");
+    }
 }
 
 fn get_ascii_logo() -> String {
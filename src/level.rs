@@ -1,18 +1,120 @@
 // Traits for working with levels
 
+use std::{collections::BTreeMap, fmt, sync::Arc, time::Duration};
+
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::{
+    bitcoin::{CtfFramework, FrameworkConfig},
+    scoring::{ScoringPolicy, StandardScoring},
+};
 
+/// A level that has been set up and is ready to be played
 #[async_trait]
 pub trait Level: Send + Sync {
-    async fn setup() -> Result<Self>
-    where
-        Self: Sized; // includes code to spin up regtest and setup
-    async fn run(&self) -> Result<bool>; // includes code to watch for transactions
+    async fn run(&self) -> Result<LevelOutcome>; // includes code to watch for transactions
     async fn cleanup(&self) -> Result<()>; // includes code to award points and clean up
-    fn print_problem_statement();
+    fn ctf_framework(&self) -> &CtfFramework; // regtest node the level is played on
+    /// reference solution, built from what the player is shown
+    #[cfg(feature = "solvers")]
+    fn solver(&self) -> Result<Box<dyn crate::solvers::Solver>> {
+        anyhow::bail!("This level has no reference solver yet")
+    }
+}
+
+/// Describes a level and knows how to set it up.
+/// Registered in the level [`Registry`] and held as `Box<dyn LevelFactory>`.
+#[async_trait]
+pub trait LevelFactory: Send + Sync {
+    fn info(&self) -> &LevelInfo;
+    fn print_problem_statement(&self);
+    fn scoring(&self) -> &dyn ScoringPolicy {
+        &StandardScoring::DEFAULT
+    }
+    async fn setup(&self, config: &FrameworkConfig) -> Result<Box<dyn Level>>; // includes code to spin up regtest and setup
+}
+
+/// Metadata of a level, used by the runner and stats
+pub struct LevelInfo {
+    pub number: u32,
+    pub id: &'static str,
+    pub title: &'static str,
+    pub difficulty: Difficulty,
+    pub learning_objectives: &'static [&'static str],
+    pub time_limit: Duration,
+    pub max_score: u32,
+    /// hints revealed one by one after each failed attempt
+    pub hints: &'static [&'static str],
+    /// levels that must be passed before this one can be played
+    pub prerequisites: &'static [u32],
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let difficulty = match self {
+            Self::Easy => "Easy",
+            Self::Medium => "Medium",
+            Self::Hard => "Hard",
+            Self::Expert => "Expert",
+        };
+        f.write_str(difficulty)
+    }
+}
+
+/// Table of every playable level keyed by its number
+#[derive(Default)]
+pub struct Registry {
+    levels: BTreeMap<u32, Arc<dyn LevelFactory>>,
+}
+
+impl Registry {
+    pub fn register(&mut self, level: Box<dyn LevelFactory>) {
+        let number = level.info().number;
+        assert!(
+            !self.levels.contains_key(&number),
+            "level {number} is registered twice"
+        );
+        self.levels.insert(number, Arc::from(level));
+    }
+
+    pub fn get(&self, number: u32) -> Option<&dyn LevelFactory> {
+        self.levels.get(&number).map(AsRef::as_ref)
+    }
+
+    /// The level as an owned handle, for setups running on their own thread
+    pub fn shared(&self, number: u32) -> Option<Arc<dyn LevelFactory>> {
+        self.levels.get(&number).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn LevelFactory> {
+        self.levels.values().map(AsRef::as_ref)
+    }
+}
+
+/// Objectives a level checked once it has been played
+pub struct LevelOutcome {
+    pub objectives_met: u32,
+    pub objectives_total: u32,
 }
 
-pub async fn start_level<T: Level>() -> Result<T> {
-    T::setup().await
+impl LevelOutcome {
+    /// Fraction of the objectives met, between 0 and 1
+    pub fn completion(&self) -> f64 {
+        if self.objectives_total == 0 {
+            return 0.0;
+        }
+        f64::from(self.objectives_met.min(self.objectives_total)) / f64::from(self.objectives_total)
+    }
 }
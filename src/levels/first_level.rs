@@ -1,79 +1,124 @@
-use core::time;
-use std::{io::Write, thread};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use bitcoin::{
     key::{Keypair, Secp256k1},
     secp256k1::{All, SecretKey},
-    taproot::{TaprootBuilder, TaprootSpendInfo},
-    Address, Amount, Network, Transaction, TxOut,
+    taproot::TaprootSpendInfo,
+    Address, Amount, Network, OutPoint, Transaction, TxOut, Txid,
 };
-use bitcoind::bitcoincore_rpc::{json::ScanTxOutRequest, RawTx, RpcApi};
+use bitcoind::bitcoincore_rpc::{Client, RawTx, RpcApi};
 use colored::Colorize;
 use rand::Rng;
-
+use tokio::sync::broadcast::error::TryRecvError;
 
 use crate::{
-    bitcoin::{add_signature, CtfFramework, TransactionBuilder},
+    bitcoin::{
+        add_signature, tapscript_tree, ChainEvent, ChainTemplate, CtfFramework, FrameworkConfig,
+        MiningMode, PlayerWallet, SmallNonce, Topology, TransactionBuilder,
+    },
     constrants::PROJECTED_FEE,
-    level::Level,
+    level::{Difficulty, Level, LevelFactory, LevelInfo, LevelOutcome},
+    timer::Countdown,
     utils::{print_failure_messege, print_success_messege},
 };
 
-
 // Contants
 const TX_WAIT_TIME: u64 = 60;
+const ALICE_UTXOS: u32 = 17;
+const MINING_INTERVAL: Duration = Duration::from_secs(5);
+/// fee a sweep may pay per UTXO, anything more has to land in the player wallet
+const MAX_SWEEP_FEE: Amount = Amount::from_sat(100_000);
+/// Alice's wallet picks its nonces from a single byte
+const ALICE_MAX_NONCE: u64 = u8::MAX as u64;
+
+/// a hundred blocks for the coinbases to mature, and one more to spend
+const LEVEL_ONE_CHAIN: ChainTemplate = ChainTemplate {
+    name: LEVEL_ONE_INFO.id,
+    blocks: 101,
+};
+
+const LEVEL_ONE_INFO: LevelInfo = LevelInfo {
+    number: 1,
+    id: "phishy-wallet",
+    title: "Phishy Wallet",
+    difficulty: Difficulty::Easy,
+    learning_objectives: &[
+        "Understand how Schnorr signatures are produced for Taproot key path spends",
+        "See why a weak nonce leaks the private key",
+        "Double spend an unconfirmed transaction from the mempool",
+    ],
+    time_limit: Duration::from_secs(TX_WAIT_TIME),
+    max_score: 100,
+    hints: &[
+        "Alice's wallet draws its signing nonce from a tiny range. Brute force it.",
+        "Once the nonce k is known, the key follows from s = k + e * x (mod n).",
+        "Alice's transaction is only broadcast once time runs out. Spend her UTXOs to your wallet before that.",
+    ],
+    prerequisites: &[],
+};
+
+pub struct LevelOneFactory;
 
 pub struct LevelOne {
     target_tx: Transaction,
+    /// outputs spent by `target_tx`, in input order
+    prevouts: Vec<TxOut>,
     ctf_framework: CtfFramework,
+    /// chain height once the level has been set up
+    start_height: u64,
 }
 
 #[async_trait]
-impl Level for LevelOne {
-    async fn setup() -> Result<Self> {
-        // spin up regtest
-        let ctf_framework = CtfFramework::new()?;
+impl LevelFactory for LevelOneFactory {
+    fn info(&self) -> &LevelInfo {
+        &LEVEL_ONE_INFO
+    }
+
+    async fn setup(&self, config: &FrameworkConfig) -> Result<Box<dyn Level>> {
+        // spin up regtest, starting from a chain with matured coins to fund the level
+        let ctf_framework = CtfFramework::new(config, &LEVEL_ONE_CHAIN, &Topology::SINGLE).await?;
         let client = &ctf_framework.bitcoind.client;
+        let mut rng = ctf_framework.rng("level-one");
 
         // generate a keypair
-        let (secp, kp, address, tr_spend_info) = level_setup();
-
-        // fund generated keypair with 17Btc
-        client.generate_to_address(118, &address)?;
-
-        // compute recieve address
-        let reciever_address = client
-            .get_new_address(None, None)
-            .unwrap()
-            .require_network(bitcoind::bitcoincore_rpc::bitcoin::Network::Regtest)
-            .unwrap();
-
-        // get balance and verify
-        let mut utxos_result = client.scan_tx_out_set_blocking(&[ScanTxOutRequest::Single(
-            format!("tr({})", &kp.x_only_public_key().0),
-        )])?;
-        utxos_result.unspents.sort_by_key(|utxo| utxo.height);
-
+        let (secp, kp, address, tr_spend_info) = level_setup(&mut rng);
+
+        // fund generated keypair with 17 utxos of 1Btc and confirm them
+        let funding = ctf_framework.fund(vec![
+            TxOut {
+                value: Amount::ONE_BTC,
+                script_pubkey: address.script_pubkey(),
+            };
+            ALICE_UTXOS as usize
+        ])?;
+        let funding_txid = funding.compute_txid();
+
+        // compute recieve address, Alice moves her coins to another key of hers
+        let (_, _, reciever_address, _) = level_setup(&mut rng);
+
+        let alice_utxos: Vec<(u32, &TxOut)> = (0..)
+            .zip(&funding.output)
+            .filter(|(_, output)| output.script_pubkey == address.script_pubkey())
+            .collect();
+        let total_amount: Amount = alice_utxos.iter().map(|(_, output)| output.value).sum();
 
         // Generate a new tx with 17 outputs all pointing to some random address.
-        let mut tx_builder = TransactionBuilder::new(utxos_result.total_amount - PROJECTED_FEE);
+        let mut tx_builder = TransactionBuilder::new(total_amount - PROJECTED_FEE);
 
         let mut prevouts = Vec::new();
         let mut amount = Amount::from_sat(0);
 
-        for (added_inputs, utxo) in utxos_result.unspents.into_iter().enumerate() {
-            if added_inputs >= 17 {
-                break;
-            }
-            tx_builder.add_input(utxo.txid, utxo.vout);
-            prevouts.push(TxOut {
-                script_pubkey: utxo.script_pub_key,
-                value: utxo.amount,
-            });
+        for (vout, utxo) in alice_utxos {
+            tx_builder.add_input(funding_txid, vout);
+            prevouts.push(utxo.clone());
 
-            amount += utxo.amount;
+            amount += utxo.value;
         }
 
         let inputs = tx_builder.transaction.input.clone();
@@ -83,6 +128,10 @@ impl Level for LevelOne {
         tx.output[0].value = amount - Amount::from_sat((tx.vsize() * 2).try_into().unwrap());
         tx.output[0].script_pubkey = reciever_address.script_pubkey();
 
+        let mut alice_signer = SmallNonce {
+            max: ALICE_MAX_NONCE,
+            rng,
+        };
         for (input_idx, _) in inputs.iter().enumerate() {
             add_signature(
                 &mut tx,
@@ -90,6 +139,7 @@ impl Level for LevelOne {
                 &prevout_refs,
                 kp.secret_key(),
                 Some(&tr_spend_info.tap_tweak().to_scalar()),
+                &mut alice_signer,
                 &secp,
             )?;
         }
@@ -97,83 +147,259 @@ impl Level for LevelOne {
         println!("\n{}", "Transaction Hex:".cyan().bold());
         println!("{}", tx.raw_hex().bright_magenta());
 
+        let start_height = client.get_block_count()?;
+
         // Sign the Tx and cache/Log the tx.
-        Ok(Self {
+        Ok(Box::new(LevelOne {
             target_tx: tx,
+            prevouts,
+            start_height,
             ctf_framework,
-        })
+        }))
     }
 
-    async fn run(&self) -> Result<bool> {
-        // await for 1 minute and submit tx to regtest
-        // if succeeds, return [false]
-        // if not succeeds,
-        // and if utxos_spent/17 > 6/10, return [true] else return false
+    fn print_problem_statement(&self) {
+        println!("\n{}", level_one_title().bright_green().bold());
+        println!("{}", "===============".bright_green());
 
-        println!("\n{}", "Time remaining:".green().bold());
+        println!("\n{}", "Problem Statement:".yellow().bold());
+        println!(
+            "{}",
+            "Alice has a wallet, but a phishy wallet draws its signing nonce from a single byte."
+                .bright_white()
+        );
+        println!(
+            "{}",
+            "Note: All transactions signed by Alice's wallet are Taproot addresses.".bright_white()
+        );
+        println!(
+            "{}",
+            "Her transaction has entered the mempool and will be confirmed in one minute."
+                .bright_white()
+        );
 
-        // Countdown timer
-        for i in (1..=TX_WAIT_TIME).rev() {
-            print!("\r{i:02} seconds");
-            std::io::stdout().flush().unwrap();
-            thread::sleep(time::Duration::from_secs(1));
-        }
+        println!("\n{}", "Your Mission:".red().bold());
+        println!("{}", "You have one minute to decode the transaction and drain Alice's funds from her wallet.".bright_white());
+        println!(
+            "{}",
+            "Only coins sent to your player wallet count as stolen.".bright_white()
+        );
+    }
+}
+
+#[async_trait]
+impl Level for LevelOne {
+    async fn run(&self) -> Result<LevelOutcome> {
+        // count down for 1 minute (or until every utxo is stolen),
+        // count the utxos the player managed to steal and then submit Alice's tx to regtest.
+        // meanwhile transactions sent by the player get mined as they show up.
+        // watching starts first, spends already in the mempool are reported before any is mined
+        let (_watcher, mut events) = self.ctf_framework.watch_chain()?;
+        let miner = self
+            .ctf_framework
+            .start_miner(MiningMode::MempoolNonEmpty(MINING_INTERVAL))?;
+        let alice_outpoints: HashSet<OutPoint> = self
+            .target_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect();
+        let counter = Arc::new(StolenCounter::new(
+            &self.ctf_framework,
+            self.target_tx.clone(),
+            self.prevouts.clone(),
+            self.start_height,
+        )?);
+
+        // only recount once one of Alice's outpoints got spent
+        let mut touched = false;
+        let mut countdown = Countdown::new(LEVEL_ONE_INFO.time_limit);
+        let clock = countdown.control();
+        let is_solved = {
+            let counter = Arc::clone(&counter);
+            move || {
+                loop {
+                    match events.try_recv() {
+                        Ok(ChainEvent::OutpointSpent { outpoint, .. }) => {
+                            touched |= alice_outpoints.contains(&outpoint);
+                        }
+                        Ok(_) => {}
+                        Err(TryRecvError::Lagged(_)) => touched = true,
+                        Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                    }
+                }
+                if !std::mem::take(&mut touched) {
+                    return Ok(false);
+                }
+                // the player's clock doesn't run while the level recounts,
+                // nor while the node doesn't answer
+                let started = Instant::now();
+                let Ok(stolen) = counter.count() else {
+                    clock.pause();
+                    touched = true;
+                    return Ok(false);
+                };
+                clock.resume();
+                clock.extend(started.elapsed());
+                Ok(stolen == ALICE_UTXOS)
+            }
+        };
+        countdown
+            .run(&self.ctf_framework.session.rpc, is_solved)
+            .await?;
 
-        let result = self
+        let stolen = tokio::task::spawn_blocking(move || counter.count()).await??;
+
+        // Alice's transaction only goes through if none of its inputs were double spent
+        let _ = self
             .ctf_framework
             .bitcoind
             .client
             .send_raw_transaction(&self.target_tx);
+        miner.mine(1).await?;
 
         println!("\n");
-        if result.is_ok() {
+        if stolen == 0 {
             print_failure_messege();
-            Ok(false)
         } else {
             print_success_messege();
-            Ok(true)
         }
+        println!(
+            "{}",
+            format!("You stole {stolen} out of Alice's {ALICE_UTXOS} UTXOs.").bright_white()
+        );
 
-        // Ok(true)
+        Ok(LevelOutcome {
+            objectives_met: stolen,
+            objectives_total: ALICE_UTXOS,
+        })
     }
 
     async fn cleanup(&self) -> Result<()> {
-        // award points and clean up the regtest save the game state.
-        CtfFramework::clean()?;
+        // nothing to clean up here, the regtest node and its data directory
+        // are torn down when `ctf_framework` is dropped.
         Ok(())
     }
 
-    fn print_problem_statement() {
-        println!("\n{}", level_one_title().bright_green().bold());
-        println!("{}", "===============".bright_green());
+    fn ctf_framework(&self) -> &CtfFramework {
+        &self.ctf_framework
+    }
 
-        println!("\n{}", "Problem Statement:".yellow().bold());
-        println!("{}", "Alice has a wallet, but a phishy wallet uses 1 bit security (nonce) to sign transactions.".bright_white());
-        println!(
-            "{}",
-            "Note: All transactions signed by Alice's wallet are Taproot addresses.".bright_white()
-        );
-        println!(
-            "{}",
-            "Her transaction has entered the mempool and will be confirmed in one minute."
-                .bright_white()
-        );
+    #[cfg(feature = "solvers")]
+    fn solver(&self) -> Result<Box<dyn crate::solvers::Solver>> {
+        Ok(Box::new(crate::solvers::LevelOneSolver {
+            target_tx: self.target_tx.clone(),
+        }))
+    }
+}
 
-        println!("\n{}", "Your Mission:".red().bold());
-        println!("{}", "You have one minute to decode the transaction and drain Alice's funds from her wallet.".bright_white());
+/// Counts Alice's UTXOs stolen by the player.
+/// Talks to the node on clients of its own, so it can count from a blocking thread.
+struct StolenCounter {
+    node: Client,
+    /// client bound to the player wallet
+    wallet: Client,
+    target_tx: Transaction,
+    /// outputs spent by `target_tx`, in input order
+    prevouts: Vec<TxOut>,
+    /// chain height once the level has been set up
+    start_height: u64,
+}
+
+impl StolenCounter {
+    fn new(
+        ctf_framework: &CtfFramework,
+        target_tx: Transaction,
+        prevouts: Vec<TxOut>,
+        start_height: u64,
+    ) -> Result<Self> {
+        let rpc = &ctf_framework.session.rpc;
+        Ok(Self {
+            node: rpc.node_client()?,
+            wallet: rpc.wallet_client()?,
+            target_tx,
+            prevouts,
+            start_height,
+        })
+    }
+
+    /// Counts the inputs of Alice's transaction that were spent by another
+    /// transaction (in the mempool or mined since setup) paying them to the player wallet.
+    /// A transaction sweeping some of Alice's UTXOs only steals them if what it pays
+    /// the player covers their value, less `MAX_SWEEP_FEE` per UTXO.
+    fn count(&self) -> Result<u32> {
+        let spenders = self.spending_transactions()?;
+        let target_txid = self.target_tx.compute_txid();
+
+        // Alice's UTXOs and the value they hold, by the transaction spending them
+        let mut swept: HashMap<Txid, (&Transaction, u32, Amount)> = HashMap::new();
+        for (input, prevout) in self.target_tx.input.iter().zip(&self.prevouts) {
+            let Some(spender) = spenders.get(&input.previous_output) else {
+                continue;
+            };
+            let txid = spender.compute_txid();
+            if txid == target_txid {
+                continue;
+            }
+            let (_, utxos, value) = swept.entry(txid).or_insert((spender, 0, Amount::ZERO));
+            *utxos += 1;
+            *value += prevout.value;
+        }
+
+        let mut stolen = 0;
+        for (spender, utxos, value) in swept.into_values() {
+            let fees = MAX_SWEEP_FEE * u64::from(utxos);
+            if self.paid_to_player(spender)? + fees >= value {
+                stolen += utxos;
+            }
+        }
+
+        Ok(stolen)
+    }
+
+    /// Maps every outpoint spent since setup to the transaction spending it
+    fn spending_transactions(&self) -> Result<HashMap<OutPoint, Transaction>> {
+        let client = &self.node;
+
+        let mut transactions = Vec::new();
+        for height in (self.start_height + 1)..=client.get_block_count()? {
+            let block = client.get_block(&client.get_block_hash(height)?)?;
+            transactions.extend(block.txdata);
+        }
+        for txid in client.get_raw_mempool()? {
+            transactions.push(client.get_raw_transaction(&txid, None)?);
+        }
+
+        let mut spenders = HashMap::new();
+        for tx in transactions {
+            for input in &tx.input {
+                spenders.insert(input.previous_output, tx.clone());
+            }
+        }
+        Ok(spenders)
+    }
+
+    /// Value of the outputs of `tx` landing in the player wallet
+    fn paid_to_player(&self, tx: &Transaction) -> Result<Amount> {
+        let mut paid = Amount::ZERO;
+        for output in &tx.output {
+            if PlayerWallet::owned_by(&self.wallet, &output.script_pubkey)? {
+                paid += output.value;
+            }
+        }
+        Ok(paid)
     }
 }
 
-pub fn level_setup() -> (Secp256k1<All>, Keypair, Address, TaprootSpendInfo) {
+pub fn level_setup(rng: &mut impl Rng) -> (Secp256k1<All>, Keypair, Address, TaprootSpendInfo) {
     let secp = Secp256k1::new();
-    let mut rng = rand::thread_rng();
 
     let data: [u8; 32] = rng.gen();
     let keypair = SecretKey::from_slice(&data).unwrap().keypair(&secp);
 
-    let taproot_spend_info: TaprootSpendInfo = TaprootBuilder::new()
-        .finalize(&secp, keypair.x_only_public_key().0)
-        .expect("Failed to finalize script");
+    let taproot_spend_info: TaprootSpendInfo =
+        tapscript_tree(&secp, keypair.x_only_public_key().0, &[])
+            .expect("Failed to finalize script");
 
     let new_payout_address: Address =
         Address::p2tr_tweaked(taproot_spend_info.output_key(), Network::Regtest);
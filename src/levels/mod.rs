@@ -1,4 +1,14 @@
 mod first_level;
 mod second_level;
 
-pub use first_level::LevelOne;
+use crate::level::Registry;
+
+pub use first_level::LevelOneFactory;
+
+/// Builds the table of playable levels.
+/// Adding a level only requires registering it here.
+pub fn registry() -> Registry {
+    let mut registry = Registry::default();
+    registry.register(Box::new(LevelOneFactory));
+    registry
+}
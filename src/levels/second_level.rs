@@ -2,7 +2,10 @@ use anyhow::Result;
 use async_trait::async_trait;
 use bitcoin::Transaction;
 
-use crate::{bitcoin::CtfFramework, level::Level};
+use crate::{
+    bitcoin::CtfFramework,
+    level::{Level, LevelOutcome},
+};
 
 #[allow(dead_code)]
 pub struct LevelTwo {
@@ -12,11 +15,7 @@ pub struct LevelTwo {
 
 #[async_trait]
 impl Level for LevelTwo {
-    async fn setup() -> Result<Self> {
-        todo!()
-    }
-
-    async fn run(&self) -> Result<bool> {
+    async fn run(&self) -> Result<LevelOutcome> {
         todo!()
     }
 
@@ -24,5 +23,7 @@ impl Level for LevelTwo {
         todo!()
     }
 
-    fn print_problem_statement() {}
+    fn ctf_framework(&self) -> &CtfFramework {
+        &self.ctf_framework
+    }
 }
@@ -1,5 +1,6 @@
+#![allow(clippy::multiple_crate_versions, clippy::cargo_common_metadata)]
+
 use anyhow::Result;
-use bitcoin::CtfFramework;
 use clap::Parser;
 
 mod bitcoin;
@@ -8,7 +9,12 @@ mod constrants;
 mod ctf;
 mod level;
 mod levels;
+mod scoring;
+#[cfg(feature = "solvers")]
+mod solvers;
 mod state;
+mod stats;
+mod timer;
 mod utils;
 
 #[tokio::main]
@@ -18,7 +24,6 @@ async fn main() -> Result<()> {
     // initialize cli
     let cli = cli::Cli::parse();
 
-    let mut ctf = ctf::Ctf::new()?;
-    ctf.run(cli).await?;
-    CtfFramework::clean()
+    let mut ctf = ctf::Ctf::new(&cli)?;
+    ctf.run(cli).await
 }
@@ -0,0 +1,174 @@
+// Scoring: turns how a level was played into points
+
+use std::time::Duration;
+
+use crate::{constrants::PASSING_PERCENTAGE, level::LevelOutcome};
+
+/// Everything known about a single attempt at a level once it has been played
+pub struct Attempt {
+    /// objectives reported by the level
+    pub outcome: LevelOutcome,
+    pub time_used: Duration,
+    pub time_limit: Duration,
+    /// hints revealed to the player before this attempt
+    pub hints_used: u32,
+    /// attempts made so far, this one included
+    pub attempts: u32,
+    /// replay of a level already passed, hints and retries are not penalized
+    pub replay: bool,
+}
+
+/// Points awarded for an attempt
+pub struct Score {
+    pub points: u32,
+    pub max_points: u32,
+    /// whether the attempt is good enough to unlock the next level, see [`passes`]
+    pub passed: bool,
+}
+
+/// The pass rule of every level: at least `PASSING_PERCENTAGE` of its points.
+/// Used both when an attempt is scored and when prerequisites are checked.
+pub const fn passes(points: u32, max_points: u32) -> bool {
+    points * 100 >= max_points * PASSING_PERCENTAGE
+}
+
+/// Converts an [`Attempt`] into points, each level can bring its own policy
+pub trait ScoringPolicy: Send + Sync {
+    fn score(&self, attempt: &Attempt, max_score: u32) -> Score;
+}
+
+/// Awards points proportionally to the objectives met, minus a penalty
+/// for the time used and for every hint revealed and every retry.
+/// With a `max_penalty` of 40% meeting every objective always passes.
+#[allow(clippy::struct_field_names)] // every field is a penalty
+pub struct StandardScoring {
+    /// percentage of the points lost when the whole time limit is used
+    pub time_penalty: u32,
+    /// percentage of the points lost per hint used
+    pub hint_penalty: u32,
+    /// percentage of the points lost per retry
+    pub retry_penalty: u32,
+    /// upper bound of the total penalty (in percentage)
+    pub max_penalty: u32,
+}
+
+impl StandardScoring {
+    pub const DEFAULT: Self = Self {
+        time_penalty: 10,
+        hint_penalty: 10,
+        retry_penalty: 5,
+        max_penalty: 40,
+    };
+}
+
+impl ScoringPolicy for StandardScoring {
+    fn score(&self, attempt: &Attempt, max_score: u32) -> Score {
+        let completion = attempt.outcome.completion();
+        let time_fraction = if attempt.time_limit.is_zero() {
+            0.0
+        } else {
+            (attempt.time_used.as_secs_f64() / attempt.time_limit.as_secs_f64()).min(1.0)
+        };
+
+        let fixed_penalty = if attempt.replay {
+            0
+        } else {
+            attempt.hints_used * self.hint_penalty
+                + attempt.attempts.saturating_sub(1) * self.retry_penalty
+        };
+        let penalty = f64::from(self.time_penalty)
+            .mul_add(time_fraction, f64::from(fixed_penalty))
+            .min(f64::from(self.max_penalty));
+
+        let points = f64::from(max_score) * completion * (100.0 - penalty) / 100.0;
+        // points is within 0..=max_score so the cast can't truncate
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let points = points.round() as u32;
+
+        Score {
+            points,
+            max_points: max_score,
+            passed: passes(points, max_score),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(objectives_met: u32, time_used: u64, hints_used: u32, attempts: u32) -> Attempt {
+        Attempt {
+            outcome: LevelOutcome {
+                objectives_met,
+                objectives_total: 10,
+            },
+            time_used: Duration::from_secs(time_used),
+            time_limit: Duration::from_secs(100),
+            hints_used,
+            attempts,
+            replay: false,
+        }
+    }
+
+    fn score(attempt: &Attempt) -> Score {
+        StandardScoring::DEFAULT.score(attempt, 100)
+    }
+
+    #[test]
+    fn pass_rule_is_inclusive() {
+        assert!(passes(60, 100));
+        assert!(!passes(59, 100));
+        assert!(passes(0, 0));
+    }
+
+    #[test]
+    fn perfect_attempt_scores_everything() {
+        let score = score(&attempt(10, 0, 0, 1));
+        assert_eq!(score.points, 100);
+        assert!(score.passed);
+    }
+
+    #[test]
+    fn penalties_add_up() {
+        // half the time (5%), one hint (10%) and two retries (10%)
+        assert_eq!(score(&attempt(10, 50, 1, 3)).points, 75);
+    }
+
+    #[test]
+    fn penalty_is_capped() {
+        let score = score(&attempt(10, 100, 5, 10));
+        assert_eq!(score.points, 60);
+        assert!(score.passed);
+    }
+
+    #[test]
+    fn time_over_the_limit_counts_as_the_limit() {
+        assert_eq!(score(&attempt(10, 500, 0, 1)).points, 90);
+    }
+
+    #[test]
+    fn penalties_can_fail_a_partial_attempt() {
+        assert!(score(&attempt(6, 0, 0, 1)).passed);
+        let penalized = score(&attempt(6, 100, 0, 1));
+        assert_eq!(penalized.points, 54);
+        assert!(!penalized.passed);
+    }
+
+    #[test]
+    fn scored_and_prerequisite_rules_agree() {
+        for met in 0..=10 {
+            for hints_used in 0..4 {
+                let score = score(&attempt(met, 30, hints_used, 2));
+                assert_eq!(score.passed, passes(score.points, score.max_points));
+            }
+        }
+    }
+
+    #[test]
+    fn replays_only_pay_for_time() {
+        let mut replay = attempt(10, 50, 2, 4);
+        replay.replay = true;
+        assert_eq!(score(&replay).points, 95);
+    }
+}
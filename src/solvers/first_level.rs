@@ -0,0 +1,193 @@
+// Level One Solver
+// Alice's nonces fit in a single byte: find the nonce of one of her signatures,
+// recover her key from s = k + e * x and sweep her coins to the player wallet
+// before her transaction confirms.
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bitcoin::{
+    hashes::Hash,
+    key::{Parity, Secp256k1, XOnlyPublicKey},
+    secp256k1::{schnorr, All, Scalar, SecretKey},
+    sighash::{Prevouts, SighashCache},
+    Amount, TapSighashType, Transaction, TxOut,
+};
+use bitcoind::bitcoincore_rpc::RpcApi;
+
+use super::Solver;
+use crate::bitcoin::{
+    add_signature, invert, scalar_from_u64, schnorr_challenge, CtfFramework, HonestSigner,
+    TransactionBuilder,
+};
+
+/// largest nonce Alice's wallet picks
+const MAX_NONCE: u64 = u8::MAX as u64;
+/// paid by the sweep, Alice's transaction is only broadcast once the level is over
+const SWEEP_FEE: Amount = Amount::from_sat(10_000);
+
+pub struct LevelOneSolver {
+    /// Alice's signed transaction, as printed to the player
+    pub target_tx: Transaction,
+}
+
+#[async_trait]
+impl Solver for LevelOneSolver {
+    async fn solve(&self, ctf_framework: &CtfFramework) -> Result<()> {
+        let client = &ctf_framework.bitcoind.client;
+        let secp = Secp256k1::new();
+
+        let prevouts = self
+            .target_tx
+            .input
+            .iter()
+            .map(|input| {
+                let outpoint = input.previous_output;
+                let utxo = client
+                    .get_tx_out(&outpoint.txid, outpoint.vout, Some(false))?
+                    .with_context(|| format!("{outpoint} is already spent"))?;
+                Ok(TxOut {
+                    value: utxo.value,
+                    script_pubkey: utxo.script_pub_key.script()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let prevout_refs: Vec<&TxOut> = prevouts.iter().collect();
+
+        // every input is signed by the same key, one signature is enough
+        let sighash = SighashCache::new(&self.target_tx).taproot_key_spend_signature_hash(
+            0,
+            &Prevouts::All(&prevout_refs),
+            TapSighashType::All,
+        )?;
+        let output_key = XOnlyPublicKey::from_slice(&prevouts[0].script_pubkey.as_bytes()[2..])?;
+        let witness = self.target_tx.input[0]
+            .witness
+            .nth(0)
+            .context("Alice's input has no signature")?;
+        let signature = schnorr::Signature::from_slice(&witness[..64])?;
+        let secret = recover_key(
+            &secp,
+            &signature,
+            &output_key,
+            sighash.as_byte_array(),
+            MAX_NONCE,
+        )?;
+
+        let total: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+        let mut tx_builder = TransactionBuilder::new(total - SWEEP_FEE);
+        for input in &self.target_tx.input {
+            tx_builder.add_input(input.previous_output.txid, input.previous_output.vout);
+        }
+        let mut sweep = tx_builder.build();
+        sweep.output[0].script_pubkey = ctf_framework.player.address.script_pubkey();
+
+        // the recovered key already is the tweaked output key
+        for input_idx in 0..sweep.input.len() {
+            add_signature(
+                &mut sweep,
+                input_idx,
+                &prevout_refs,
+                secret,
+                None,
+                &mut HonestSigner {
+                    rng: rand::thread_rng(),
+                },
+                &secp,
+            )?;
+        }
+        client.send_raw_transaction(&sweep)?;
+        Ok(())
+    }
+}
+
+/// Tries every nonce up to `max_nonce` and returns the key the signature leaks:
+/// x = (s - k) / e
+#[allow(clippy::many_single_char_names)] // names follow BIP340
+fn recover_key(
+    secp: &Secp256k1<All>,
+    signature: &schnorr::Signature,
+    public_key: &XOnlyPublicKey,
+    msg: &[u8; 32],
+    max_nonce: u64,
+) -> Result<SecretKey> {
+    let signature = signature.serialize();
+    let (r, s) = signature.split_at(32);
+    let s = SecretKey::from_slice(s)?;
+    let r = XOnlyPublicKey::from_slice(r)?;
+    let e = schnorr_challenge(&r, public_key, msg);
+    let e_inverse = invert(SecretKey::from_slice(&e.to_be_bytes())?)?;
+
+    for nonce in 1..=max_nonce {
+        let mut k = SecretKey::from_slice(&scalar_from_u64(nonce).to_be_bytes())?;
+        let (point, parity) = k.x_only_public_key(secp);
+        if point != r {
+            continue;
+        }
+        // the signer negated the nonce to get an even R
+        if parity == Parity::Odd {
+            k = k.negate();
+        }
+        let x = s
+            .add_tweak(&Scalar::from(k.negate()))?
+            .mul_tweak(&Scalar::from(e_inverse))?;
+        if x.x_only_public_key(secp).0 == *public_key {
+            return Ok(x);
+        }
+    }
+    bail!("No nonce up to {max_nonce} matches the signature")
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{key::Keypair, secp256k1::Message};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::bitcoin::{sign_schnorr_with_nonce, Signer, SmallNonce};
+
+    #[test]
+    fn recovers_keys_behind_small_nonces() {
+        let secp = Secp256k1::new();
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let mut signer = SmallNonce {
+            max: MAX_NONCE,
+            rng: ChaCha20Rng::seed_from_u64(2),
+        };
+        // enough keys and nonces to hit both parities of each
+        for idx in 0..16 {
+            let keypair = Keypair::new(&secp, &mut rng);
+            let msg = Message::from_digest([idx; 32]);
+            let signature = signer.sign_schnorr(&secp, &msg, &keypair).unwrap();
+
+            let public_key = keypair.x_only_public_key().0;
+            let x = recover_key(&secp, &signature, &public_key, msg.as_ref(), MAX_NONCE).unwrap();
+            assert_eq!(x.x_only_public_key(&secp).0, public_key);
+        }
+    }
+
+    #[test]
+    fn recovers_keys_from_chosen_nonces() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut ChaCha20Rng::seed_from_u64(3));
+        let msg = Message::from_digest([7; 32]);
+        let public_key = keypair.x_only_public_key().0;
+        for nonce in [1, 2, 200, MAX_NONCE] {
+            let k = SecretKey::from_slice(&scalar_from_u64(nonce).to_be_bytes()).unwrap();
+            let signature = sign_schnorr_with_nonce(&secp, &msg, &keypair, k).unwrap();
+            let x = recover_key(&secp, &signature, &public_key, msg.as_ref(), MAX_NONCE).unwrap();
+            assert_eq!(x.x_only_public_key(&secp).0, public_key);
+        }
+    }
+
+    #[test]
+    fn fails_past_the_nonce_range() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::new(&secp, &mut ChaCha20Rng::seed_from_u64(4));
+        let msg = Message::from_digest([9; 32]);
+        let k = SecretKey::from_slice(&scalar_from_u64(MAX_NONCE + 1).to_be_bytes()).unwrap();
+        let signature = sign_schnorr_with_nonce(&secp, &msg, &keypair, k).unwrap();
+        let public_key = keypair.x_only_public_key().0;
+        assert!(recover_key(&secp, &signature, &public_key, msg.as_ref(), MAX_NONCE).is_err());
+    }
+}
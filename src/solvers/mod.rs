@@ -0,0 +1,53 @@
+// Reference Solvers
+// Every level ships with a solution proving it can be won as advertised.
+// Kept out of the player build, compiled with `--features solvers`.
+
+mod first_level;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+pub use first_level::LevelOneSolver;
+
+use crate::{
+    bitcoin::{CtfFramework, FrameworkConfig},
+    level::{Level, LevelFactory, LevelOutcome},
+};
+
+/// Solution of a level, built by the level from what the player is shown
+#[async_trait]
+pub trait Solver: Send + Sync {
+    /// Recovers the secret and broadcasts the winning transactions
+    async fn solve(&self, ctf_framework: &CtfFramework) -> Result<()>;
+}
+
+/// Sets the level up, then runs it while its solver plays.
+/// Returns the outcome reported by the level along with the time spent in `run`.
+pub async fn solve(
+    level: &dyn LevelFactory,
+    config: &FrameworkConfig,
+) -> Result<(LevelOutcome, Duration)> {
+    let lvl: Arc<dyn Level> = Arc::from(level.setup(config).await?);
+    let solution = lvl.solver()?;
+    let started_at = Instant::now();
+    let mut run = tokio::spawn({
+        let lvl = Arc::clone(&lvl);
+        async move { lvl.run().await }
+    });
+
+    let solved = solution.solve(lvl.ctf_framework()).await;
+    if solved.is_err() {
+        run.abort();
+    }
+    let outcome = (&mut run).await;
+    let time_used = started_at.elapsed();
+    lvl.cleanup().await?;
+
+    solved.with_context(|| format!("Solver of level {} failed", level.info().number))?;
+    Ok((outcome.context("Level panicked while running")??, time_used))
+}
@@ -10,14 +10,43 @@ const STATE_FILE: &str = "game_state.toml";
 #[derive(Serialize, Deserialize)]
 pub struct State {
     current_level: u32,
+    /// every level the player has attempted, solved or not
     pub completed_levels: Vec<CtfLevel>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CtfLevel {
     pub level: u32,
+    /// best score achieved so far
     pub score: u32,
-    // todo fields
+    #[serde(default)]
+    pub attempts: u32,
+    /// fastest time to solve the level (in seconds)
+    #[serde(default)]
+    pub best_time: Option<u64>,
+    /// time taken by the most recent solve (in seconds)
+    #[serde(default)]
+    pub last_time: Option<u64>,
+    /// seed of every attempt, oldest first, replayable with `--seed`
+    #[serde(default)]
+    pub seeds: Vec<u64>,
+    /// hints shown to the player so far, one more after every failed attempt
+    #[serde(default)]
+    pub hints_revealed: u32,
+}
+
+impl CtfLevel {
+    const fn new(level: u32) -> Self {
+        Self {
+            level,
+            score: 0,
+            attempts: 0,
+            best_time: None,
+            last_time: None,
+            seeds: Vec::new(),
+            hints_revealed: 0,
+        }
+    }
 }
 
 impl State {
@@ -51,22 +80,65 @@ impl State {
         Ok(())
     }
 
-    pub fn current_level(&self) -> u32 {
+    pub const fn current_level(&self) -> u32 {
         self.current_level
     }
 
-    pub fn complete_level(&mut self, level: u32, score: u32) -> Result<()> {
-        let completed_level = CtfLevel { level, score };
-        self.completed_levels.push(completed_level);
-        self.current_level = level + 1;
+    /// Records an attempt at a level and the seed it was generated from, whether it was solved or not.
+    pub fn record_attempt(&mut self, level: u32, seed: u64) -> Result<()> {
+        let played_level = self.level_entry(level);
+        played_level.attempts += 1;
+        played_level.seeds.push(seed);
         self.save()
     }
 
-    #[allow(dead_code)]
+    /// Records the score of an attempt that did not pass, keeping the best score.
+    pub fn record_score(&mut self, level: u32, score: u32) -> Result<()> {
+        let played_level = self.level_entry(level);
+        played_level.score = played_level.score.max(score);
+        self.save()
+    }
+
+    /// Reveals the next hint of a level out of the `available` ones.
+    /// Returns how many hints the player has seen.
+    pub fn reveal_hint(&mut self, level: u32, available: u32) -> Result<u32> {
+        let played_level = self.level_entry(level);
+        played_level.hints_revealed = (played_level.hints_revealed + 1).min(available);
+        let revealed = played_level.hints_revealed;
+        self.save()?;
+        Ok(revealed)
+    }
+
+    /// Records a solved level, keeping the best score and time if it was solved before
+    /// and unlocking the next level.
+    pub fn complete_level(&mut self, level: u32, score: u32, time_taken: u64) -> Result<()> {
+        let completed_level = self.level_entry(level);
+        completed_level.score = completed_level.score.max(score);
+        completed_level.last_time = Some(time_taken);
+        completed_level.best_time = Some(
+            completed_level
+                .best_time
+                .map_or(time_taken, |best| best.min(time_taken)),
+        );
+        self.current_level = self.current_level.max(level + 1);
+        self.save()
+    }
+
+    pub fn get_level(&self, level: u32) -> Option<&CtfLevel> {
+        self.completed_levels.iter().find(|l| l.level == level)
+    }
+
+    fn level_entry(&mut self, level: u32) -> &mut CtfLevel {
+        let idx = if let Some(idx) = self.completed_levels.iter().position(|l| l.level == level) {
+            idx
+        } else {
+            self.completed_levels.push(CtfLevel::new(level));
+            self.completed_levels.len() - 1
+        };
+        &mut self.completed_levels[idx]
+    }
+
     pub fn get_level_score(&self, level: u32) -> Option<u32> {
-        self.completed_levels
-            .iter()
-            .find(|&l| l.level == level)
-            .map(|l| l.score)
+        self.get_level(level).map(|l| l.score)
     }
 }
@@ -0,0 +1,138 @@
+// Game statistics built from the saved game state
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{
+    level::{Difficulty, Registry},
+    state::State,
+};
+
+#[derive(Serialize)]
+pub struct Stats {
+    pub current_level: u32,
+    pub total_score: u32,
+    pub max_score: u32,
+    /// total score as a percentage of the max score
+    pub percentage: f64,
+    pub levels: Vec<LevelStats>,
+}
+
+#[derive(Serialize)]
+pub struct LevelStats {
+    pub level: u32,
+    pub id: &'static str,
+    pub title: &'static str,
+    pub difficulty: Difficulty,
+    pub locked: bool,
+    pub score: u32,
+    pub max_score: u32,
+    pub attempts: u32,
+    /// fastest time to solve (in seconds)
+    pub best_time: Option<u64>,
+    /// time taken by the most recent solve (in seconds)
+    pub last_time: Option<u64>,
+}
+
+impl Stats {
+    /// Builds the statistics for every registered level from the game state
+    pub fn new(state: &State, registry: &Registry) -> Self {
+        let levels: Vec<LevelStats> = registry
+            .iter()
+            .map(|level| {
+                let info = level.info();
+                let played = state.get_level(info.number);
+                LevelStats {
+                    level: info.number,
+                    id: info.id,
+                    title: info.title,
+                    difficulty: info.difficulty,
+                    locked: info.number > state.current_level(),
+                    score: played.map_or(0, |l| l.score),
+                    max_score: info.max_score,
+                    attempts: played.map_or(0, |l| l.attempts),
+                    best_time: played.and_then(|l| l.best_time),
+                    last_time: played.and_then(|l| l.last_time),
+                }
+            })
+            .collect();
+
+        let total_score = levels.iter().map(|l| l.score).sum();
+        let max_score = levels.iter().map(|l| l.max_score).sum();
+        let percentage = if max_score == 0 {
+            0.0
+        } else {
+            f64::from(total_score) * 100.0 / f64::from(max_score)
+        };
+
+        Self {
+            current_level: state.current_level(),
+            total_score,
+            max_score,
+            percentage,
+            levels,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize stats to JSON")
+    }
+
+    pub fn print(&self) {
+        println!("\n{}", "Game Statistics".bright_yellow().bold());
+        println!("{}", "===============".bright_yellow());
+
+        println!(
+            "\n{}",
+            format!(
+                "{:<8}{:<20}{:<12}{:<10}{:<10}{:<12}{:<12}",
+                "Level", "Title", "Difficulty", "Score", "Attempts", "Best Time", "Last Time"
+            )
+            .cyan()
+            .bold()
+        );
+        for level in &self.levels {
+            if level.locked {
+                println!(
+                    "{}",
+                    format!(
+                        "{:<8}{:<20}{:<12}{}",
+                        level.level,
+                        level.title,
+                        level.difficulty.to_string(),
+                        "🔒 locked"
+                    )
+                    .bright_black()
+                );
+                continue;
+            }
+            println!(
+                "{:<8}{:<20}{:<12}{:<10}{:<10}{:<12}{:<12}",
+                level.level,
+                level.title,
+                level.difficulty.to_string(),
+                format!("{}/{}", level.score, level.max_score),
+                level.attempts,
+                format_time(level.best_time),
+                format_time(level.last_time),
+            );
+        }
+
+        println!(
+            "\n{} {}/{} ({:.1}%)",
+            "Total Score:".green().bold(),
+            self.total_score,
+            self.max_score,
+            self.percentage
+        );
+        println!("{} {}", "Current Level:".green().bold(), self.current_level);
+    }
+}
+
+fn format_time(seconds: Option<u64>) -> String {
+    seconds.map_or_else(
+        || "-".to_string(),
+        |s| format!("{}m {:02}s", s / 60, s % 60),
+    )
+}
@@ -0,0 +1,194 @@
+// Countdown used by levels while waiting for the player
+// Ticks without blocking the runtime, watches for the win condition and
+// reads player commands while the clock runs.
+// Only levels can pause or extend the clock, players can't stop their own time limit.
+
+use std::{
+    io::{BufRead, Write},
+    sync::OnceLock,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::bitcoin::RpcConnection;
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// Why a countdown stopped
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CountdownEnd {
+    /// the win condition was met before time ran out
+    Solved,
+    /// the clock reached zero
+    Expired,
+    /// the player ended the level early
+    Submitted,
+}
+
+/// Changes asked of a running countdown
+enum ClockCommand {
+    Pause,
+    Resume,
+    Extend(Duration),
+    Submit,
+}
+
+/// Steers a countdown, also while it runs. Cheap to clone and usable from any thread,
+/// e.g. from the `is_solved` check of a level.
+#[derive(Clone)]
+pub struct CountdownControl {
+    commands: mpsc::UnboundedSender<ClockCommand>,
+}
+
+impl CountdownControl {
+    pub fn pause(&self) {
+        let _ = self.commands.send(ClockCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.commands.send(ClockCommand::Resume);
+    }
+
+    pub fn extend(&self, extra: Duration) {
+        let _ = self.commands.send(ClockCommand::Extend(extra));
+    }
+
+    /// Ends the countdown early, like the player's `submit` command
+    pub fn submit(&self) {
+        let _ = self.commands.send(ClockCommand::Submit);
+    }
+}
+
+pub struct Countdown {
+    remaining: Duration,
+    paused: bool,
+    control: CountdownControl,
+    commands: mpsc::UnboundedReceiver<ClockCommand>,
+}
+
+impl Countdown {
+    pub fn new(time_limit: Duration) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        Self {
+            remaining: time_limit,
+            paused: false,
+            control: CountdownControl { commands },
+            commands: receiver,
+        }
+    }
+
+    /// Handle pausing, resuming or extending the clock, commands are applied on the next tick
+    pub fn control(&self) -> CountdownControl {
+        self.control.clone()
+    }
+
+    /// Counts down until the clock runs out, `is_solved` returns true or the player submits.
+    /// `is_solved` is checked on every tick on a blocking thread, as it usually calls the node.
+    /// Player commands are handled as they are typed.
+    #[allow(clippy::significant_drop_tightening)] // player input is held for the whole countdown
+    pub async fn run<F>(&mut self, rpc: &RpcConnection, is_solved: F) -> Result<CountdownEnd>
+    where
+        F: FnMut() -> Result<bool> + Send + 'static,
+    {
+        println!("\n{}", "Time remaining:".green().bold());
+        println!("{}", "(type `help` for commands)".bright_black());
+
+        let mut is_solved = Some(is_solved);
+        let mut input = player_input().lock().await;
+        let mut ticker = tokio::time::interval(TICK);
+        ticker.tick().await; // first tick completes immediately
+
+        loop {
+            // commands sent since the last tick, e.g. by the check itself
+            while let Ok(command) = self.commands.try_recv() {
+                match command {
+                    ClockCommand::Pause => self.paused = true,
+                    ClockCommand::Resume => self.paused = false,
+                    ClockCommand::Extend(extra) => self.remaining += extra,
+                    ClockCommand::Submit => return Ok(CountdownEnd::Submitted),
+                }
+            }
+            self.print_remaining();
+
+            // the check moves to the blocking thread and back on every tick
+            let mut check = is_solved
+                .take()
+                .expect("check is put back after every tick");
+            let (solved, check) = tokio::task::spawn_blocking(move || (check(), check)).await?;
+            is_solved = Some(check);
+            if solved? {
+                return Ok(CountdownEnd::Solved);
+            }
+            if self.remaining.is_zero() {
+                return Ok(CountdownEnd::Expired);
+            }
+
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if !self.paused {
+                        self.remaining = self.remaining.saturating_sub(TICK);
+                    }
+                }
+                Some(line) = input.recv() => handle_command(&line, rpc, &self.control).await,
+            }
+        }
+    }
+
+    fn print_remaining(&self) {
+        let status = if self.paused { " (paused)" } else { "" };
+        print!("\r{:02} seconds{status}   ", self.remaining.as_secs());
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Handles a command typed by the player
+async fn handle_command(line: &str, rpc: &RpcConnection, control: &CountdownControl) {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("submit") => control.submit(),
+        Some("rpc") => match words.next() {
+            Some(method) => {
+                let (rpc, method) = (rpc.clone(), method.to_string());
+                let params: Vec<String> = words.map(String::from).collect();
+                let result = tokio::task::spawn_blocking(move || rpc.call(&method, &params)).await;
+                match result {
+                    Ok(Ok(result)) => println!("{result}"),
+                    Ok(Err(err)) => println!("{}", err.to_string().red()),
+                    Err(err) => println!("{}", err.to_string().red()),
+                }
+            }
+            None => println!("{}", "usage: rpc <method> [params]".red()),
+        },
+        Some("help") => print_help(),
+        Some(other) => println!("{}", format!("unknown command `{other}`").red()),
+        None => {}
+    }
+}
+
+fn print_help() {
+    println!("\n{}", "Commands:".cyan().bold());
+    println!("  rpc <method> [params]  call the level node");
+    println!("  submit                 end the level now");
+}
+
+/// Lines typed by the player.
+/// Stdin is read on a dedicated thread so a pending read never blocks the runtime.
+fn player_input() -> &'static Mutex<mpsc::UnboundedReceiver<String>> {
+    static INPUT: OnceLock<Mutex<mpsc::UnboundedReceiver<String>>> = OnceLock::new();
+    INPUT.get_or_init(|| {
+        let (tx, rx) = mpsc::unbounded_channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(rx)
+    })
+}
@@ -0,0 +1,33 @@
+// Every registered level can be won with a full score by its reference solver.
+// Needs bitcoind (see `btc-ctf doctor`), so it only runs when asked for:
+// `cargo test --features solvers -- --ignored`.
+
+#![allow(clippy::multiple_crate_versions, clippy::cargo_common_metadata)]
+#![cfg(feature = "solvers")]
+
+use std::process::Command;
+
+#[test]
+#[ignore = "needs bitcoind, run with `-- --ignored`"]
+fn every_level_is_solvable() {
+    let doctor = Command::new(env!("CARGO_BIN_EXE_btc-ctf"))
+        .arg("doctor")
+        .output()
+        .expect("Failed to run btc-ctf");
+    assert!(
+        doctor.status.success(),
+        "No supported bitcoind found:\n{}",
+        String::from_utf8_lossy(&doctor.stdout)
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_btc-ctf"))
+        .arg("solve")
+        .output()
+        .expect("Failed to run btc-ctf");
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}